@@ -0,0 +1,112 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use similar::{capture_diff_slices_deadline, Algorithm, DiffOp};
+
+/// How a span of the second source differs from the first one.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditKind {
+    /// Bytes present only in the second source.
+    Insert,
+    /// Bytes present only in the first source.
+    Delete,
+    /// Bytes of the first source replaced by others in the second one.
+    Replace,
+}
+
+impl fmt::Display for EditKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EditKind::Insert => "insert",
+            EditKind::Delete => "delete",
+            EditKind::Replace => "replace",
+        })
+    }
+}
+
+/// A span which differs once both sources are aligned.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub kind: EditKind,
+    /// Offset in the first source.
+    pub offset1: u64,
+    /// Length in the first source, 0 for an insertion.
+    #[serde(rename = "length1")]
+    pub len1: u64,
+    /// Offset in the second source.
+    pub offset2: u64,
+    /// Length in the second source, 0 for a deletion.
+    #[serde(rename = "length2")]
+    pub len2: u64,
+}
+
+/// Time after which [`align`] gives up on aligning what remains.
+pub const ALIGN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Align both sources with the Myers diff algorithm and list the spans which differ.
+///
+/// Unlike [`Differ`](crate::Differ), this tells inserted or deleted bytes
+/// apart from replaced ones, at the cost of holding both sources in memory.
+/// The algorithm is quadratic in the worst case, for dissimilar sources, so
+/// the spans still unaligned after [`ALIGN_TIMEOUT`] are reported as replaced.
+pub fn align(left: &[u8], right: &[u8]) -> Vec<Edit> {
+    align_deadline(left, right, Some(Instant::now() + ALIGN_TIMEOUT))
+}
+
+/// Same as [`align`], giving up at `deadline` instead, or never if `None`.
+pub fn align_deadline(left: &[u8], right: &[u8], deadline: Option<Instant>) -> Vec<Edit> {
+    capture_diff_slices_deadline(Algorithm::Myers, left, right, deadline)
+        .into_iter()
+        .filter_map(|op| {
+            let (kind, offset1, len1, offset2, len2) = match op {
+                DiffOp::Equal { .. } => return None,
+                DiffOp::Delete {
+                    old_index,
+                    old_len,
+                    new_index,
+                } => (EditKind::Delete, old_index, old_len, new_index, 0),
+                DiffOp::Insert {
+                    old_index,
+                    new_index,
+                    new_len,
+                } => (EditKind::Insert, old_index, 0, new_index, new_len),
+                DiffOp::Replace {
+                    old_index,
+                    old_len,
+                    new_index,
+                    new_len,
+                } => (EditKind::Replace, old_index, old_len, new_index, new_len),
+            };
+
+            Some(Edit {
+                kind,
+                offset1: offset1 as u64,
+                len1: len1 as u64,
+                offset2: offset2 as u64,
+                len2: len2 as u64,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,466 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    cmp::Ordering,
+    io::{self, Read},
+    ops::Range,
+};
+
+use crate::{Endian, Heatmap, Histogram, Mask, ParDiffer, Ranges, Stats, Tail};
+
+/// Size of the chunks read from each source.
+pub const BUFFER_SIZE: usize = 1024;
+
+/// Largest supported word size, in bytes.
+pub const MAX_WORD_SIZE: usize = 8;
+
+/// A single differing word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// Offset of the word from the start of the first source, see [`Differ::start_offset`].
+    pub offset: u64,
+    /// Size of the word in bytes, 1 unless comparing wider words.
+    pub size: usize,
+    /// Value in the first source.
+    pub left: u64,
+    /// Value in the second source.
+    pub right: u64,
+    /// Bits excluded from the comparison by a [`Mask`].
+    pub ignored: u64,
+}
+
+impl Difference {
+    /// Bits that differ between both values, except for ignored ones.
+    pub fn xor(&self) -> u64 {
+        (self.left ^ self.right) & !self.ignored
+    }
+
+    /// Whether exactly one bit differs.
+    pub fn is_bitflip(&self) -> bool {
+        is_bitflipped(self.left & !self.ignored, self.right & !self.ignored)
+    }
+
+    /// Positions of the differing bits, least significant first.
+    pub fn flipped_bits(&self) -> impl Iterator<Item = u32> {
+        let xor = self.xor();
+        (0..self.bits()).filter(move |bit| xor & (1 << bit) != 0)
+    }
+
+    /// Size of the word in bits.
+    pub fn bits(&self) -> u32 {
+        self.size as u32 * u8::BITS
+    }
+}
+
+/// Streaming comparison of two sources.
+///
+/// The sources are read in chunks of [`BUFFER_SIZE`] bytes and compared
+/// word by word until the shorter one ends. Words are single bytes unless
+/// set otherwise with [`Differ::word_size`].
+pub struct Differ<R1, R2> {
+    r1: R1,
+    r2: R2,
+    buffer1: [u8; BUFFER_SIZE],
+    buffer2: [u8; BUFFER_SIZE],
+    /// Offset of the first compared byte.
+    start: u64,
+    /// Offset of the current chunk.
+    offset: u64,
+    /// Position within the current chunk.
+    pos: usize,
+    /// Number of bytes available in both buffers.
+    len: usize,
+    /// Number of bytes read from each source.
+    read1: u64,
+    read2: u64,
+    eof: Option<Ordering>,
+    pad: Option<u8>,
+    word_size: usize,
+    endian: Endian,
+    single_bitflip_only: bool,
+    mask: Mask,
+    stats: Stats,
+}
+
+impl<R1: Read, R2: Read> Differ<R1, R2> {
+    pub fn new(r1: R1, r2: R2) -> Self {
+        Self {
+            r1,
+            r2,
+            buffer1: [0u8; BUFFER_SIZE],
+            buffer2: [0u8; BUFFER_SIZE],
+            start: 0,
+            offset: 0,
+            pos: 0,
+            len: 0,
+            read1: 0,
+            read2: 0,
+            eof: None,
+            pad: None,
+            word_size: 1,
+            endian: Endian::default(),
+            single_bitflip_only: false,
+            mask: Mask::default(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Offset reported for the first compared byte, for sources which do not
+    /// start at the beginning of their file.
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.start = offset;
+        self.offset = offset;
+        if let Some(heatmap) = &self.stats.heatmap {
+            self.stats.heatmap = Some(Heatmap::new(offset, 0, heatmap.block_size()));
+        }
+        self
+    }
+
+    /// Compare aligned words of `word_size` bytes, up to [`MAX_WORD_SIZE`].
+    ///
+    /// # Panics
+    ///
+    /// If `word_size` is not a power of two up to [`MAX_WORD_SIZE`].
+    pub fn word_size(mut self, word_size: usize) -> Self {
+        assert!(word_size.is_power_of_two() && word_size <= MAX_WORD_SIZE);
+        self.word_size = word_size;
+        self
+    }
+
+    /// Byte order of the words, little endian by default.
+    pub fn endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self.stats.histogram = Histogram::new(self.stats.histogram.word_size(), endian);
+        self
+    }
+
+    /// Report only differences of a single bit flip.
+    pub fn single_bitflip_only(mut self, enable: bool) -> Self {
+        self.single_bitflip_only = enable;
+        self
+    }
+
+    /// Exclude the bits of the mask from the comparison.
+    pub fn mask(mut self, mask: Mask) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Compare the shorter source as if padded with `fill` up to the length
+    /// of the longer one.
+    pub fn pad(mut self, fill: u8) -> Self {
+        self.pad = Some(fill);
+        self
+    }
+
+    /// Collect the flip histogram over words of `word_size` bytes instead of single bytes.
+    pub fn histogram_word_size(mut self, word_size: usize) -> Self {
+        self.stats.histogram = Histogram::new(word_size, self.endian);
+        self
+    }
+
+    /// Collect the flipped bits by blocks of `block_size` bytes, into
+    /// [`Stats::heatmap`].
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0.
+    pub fn heatmap(mut self, block_size: u64) -> Self {
+        self.stats.heatmap = Some(Heatmap::new(self.start, 0, block_size));
+        self
+    }
+
+    /// How the length of the first source compares to the second one.
+    ///
+    /// Available only once the comparison reached the end of either source.
+    pub fn eof_ordering(&self) -> Option<Ordering> {
+        self.eof
+    }
+
+    /// Statistics of the differences found so far.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            compared: self.compared(),
+            ..self.stats.clone()
+        }
+    }
+
+    /// Read the rest of the longer source, once the comparison is done.
+    ///
+    /// There is no tail for sources of the same length, or when padding the
+    /// shorter one.
+    pub fn tail(mut self) -> io::Result<Option<Tail>> {
+        let start = self.offset + self.len as u64;
+        let buffered = |read: u64| self.len..(read + self.start - self.offset) as usize;
+        let tail = match (self.eof, self.pad) {
+            (Some(Ordering::Greater), None) => read_tail(
+                1,
+                start,
+                &mut self.r1,
+                &mut self.buffer1,
+                buffered(self.read1),
+            )?,
+            (Some(Ordering::Less), None) => read_tail(
+                2,
+                start,
+                &mut self.r2,
+                &mut self.buffer2,
+                buffered(self.read2),
+            )?,
+            _ => return Ok(None),
+        };
+        Ok(Some(tail))
+    }
+
+    /// Coalesce differences no more than `gap` equal bytes apart into ranges.
+    pub fn ranges(self, gap: u64) -> Ranges<R1, R2> {
+        Ranges::new(self, gap)
+    }
+
+    /// Number of bytes compared so far.
+    pub fn compared(&self) -> u64 {
+        self.offset + self.pos as u64 - self.start
+    }
+
+    /// A comparison of other sources from `offset`, with the same settings.
+    pub(crate) fn fork<S1, S2>(&self, r1: S1, r2: S2, offset: u64) -> Differ<S1, S2> {
+        Differ {
+            r1,
+            r2,
+            buffer1: [0u8; BUFFER_SIZE],
+            buffer2: [0u8; BUFFER_SIZE],
+            start: offset,
+            offset,
+            pos: 0,
+            len: 0,
+            read1: 0,
+            read2: 0,
+            eof: None,
+            pad: self.pad,
+            word_size: self.word_size,
+            endian: self.endian,
+            single_bitflip_only: self.single_bitflip_only,
+            mask: self.mask.clone(),
+            stats: Stats {
+                histogram: Histogram::new(self.stats.histogram.word_size(), self.endian),
+                // Blocks of the whole comparison, merged back once the fork is done.
+                heatmap: self
+                    .stats
+                    .heatmap
+                    .as_ref()
+                    .map(|heatmap| Heatmap::new(heatmap.offset(0), 0, heatmap.block_size())),
+                ..Stats::default()
+            },
+        }
+    }
+
+    /// Read the next chunk, returning false once there is nothing left to compare.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.eof.is_some() {
+            return Ok(false);
+        }
+
+        self.offset += self.len as u64;
+        self.pos = 0;
+
+        let n1 = read_full(&mut self.r1, &mut self.buffer1)?;
+        let n2 = read_full(&mut self.r2, &mut self.buffer2)?;
+        self.read1 += n1 as u64;
+        self.read2 += n2 as u64;
+
+        self.len = match self.pad {
+            Some(fill) => {
+                let len = std::cmp::max(n1, n2);
+                self.buffer1[n1..len].fill(fill);
+                self.buffer2[n2..len].fill(fill);
+                len
+            }
+            None => std::cmp::min(n1, n2),
+        };
+
+        // EOF, as the buffers are filled unless a source ended
+        if self.len < BUFFER_SIZE {
+            self.eof = Some(self.read1.cmp(&self.read2));
+        }
+
+        Ok(self.len != 0)
+    }
+
+    /// Check whether a pair of words is a difference, accounting it in the statistics.
+    pub(crate) fn compare(&mut self, offset: u64, word: Range<usize>) -> Option<Difference> {
+        let (w1, w2) = self.words(word);
+        let v1 = self.endian.decode(w1);
+        let v2 = self.endian.decode(w2);
+        let size = w1.len();
+
+        let ignored = if self.mask.is_empty() || v1 == v2 {
+            0
+        } else {
+            let mut ignored = [0u8; MAX_WORD_SIZE];
+            for (i, bits) in ignored[..size].iter_mut().enumerate() {
+                *bits = self.mask.ignored(offset + i as u64);
+            }
+            self.endian.decode(&ignored[..size])
+        };
+
+        let (m1, m2) = (v1 & !ignored, v2 & !ignored);
+        if v1 != v2 && m1 == m2 {
+            self.stats.masked_differences += 1;
+        }
+        self.stats.masked_bits += ((v1 ^ v2) & ignored).count_ones() as u64;
+
+        let is_diff = if self.single_bitflip_only {
+            is_bitflipped(m1, m2)
+        } else {
+            m1 != m2
+        };
+        if !is_diff {
+            return None;
+        }
+
+        let difference = Difference {
+            offset,
+            size,
+            left: v1,
+            right: v2,
+            ignored,
+        };
+        self.stats.add(&difference);
+        Some(difference)
+    }
+
+    /// Offset and position within the buffers of the next pair of words,
+    /// regardless of whether they differ.
+    ///
+    /// The last word may be shorter if the length of the sources is not a
+    /// multiple of the word size.
+    pub(crate) fn next_word(&mut self) -> io::Result<Option<(u64, Range<usize>)>> {
+        while self.pos >= self.len {
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+
+        let start = self.pos;
+        self.pos = std::cmp::min(start + self.word_size, self.len);
+
+        Ok(Some((self.offset + start as u64, start..self.pos)))
+    }
+
+    /// Bytes of both sources at the given position within the buffers.
+    pub(crate) fn words(&self, word: Range<usize>) -> (&[u8], &[u8]) {
+        (&self.buffer1[word.clone()], &self.buffer2[word])
+    }
+
+    /// Skip the words which are equal in both buffers, [`MAX_WORD_SIZE`]
+    /// bytes at a time so that the position stays aligned to the word size.
+    fn skip_equal(&mut self) {
+        let words1 = self.buffer1[self.pos..self.len].chunks_exact(MAX_WORD_SIZE);
+        let words2 = self.buffer2[self.pos..self.len].chunks_exact(MAX_WORD_SIZE);
+        let equal = words1.zip(words2).take_while(|(w1, w2)| w1 == w2).count();
+        self.pos += equal * MAX_WORD_SIZE;
+    }
+
+    fn next_difference(&mut self) -> io::Result<Option<Difference>> {
+        loop {
+            self.skip_equal();
+            let Some((offset, word)) = self.next_word()? else {
+                return Ok(None);
+            };
+            if let Some(difference) = self.compare(offset, word) {
+                return Ok(Some(difference));
+            }
+        }
+    }
+}
+
+impl<'a> Differ<&'a [u8], &'a [u8]> {
+    /// Compare sources held in memory, such as memory-mapped files, in parallel.
+    pub fn parallel(self) -> ParDiffer<'a> {
+        ParDiffer::new(self)
+    }
+
+    /// Byte padding the shorter source, if any.
+    pub(crate) fn padding(&self) -> Option<u8> {
+        self.pad
+    }
+
+    /// Offset of the first compared byte.
+    pub(crate) fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Both sources, as long as nothing was read yet.
+    pub(crate) fn sources(&self) -> (&'a [u8], &'a [u8]) {
+        (self.r1, self.r2)
+    }
+}
+
+impl<R1: Read, R2: Read> Iterator for Differ<R1, R2> {
+    type Item = io::Result<Difference>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_difference().transpose()
+    }
+}
+
+/// Read the tail of source `file`, from the `buffered` bytes of `buffer` on.
+fn read_tail(
+    file: u8,
+    start: u64,
+    r: &mut impl Read,
+    buffer: &mut [u8],
+    buffered: Range<usize>,
+) -> io::Result<Tail> {
+    let mut tail = Tail::new(file, start);
+    tail.add(&buffer[buffered]);
+    loop {
+        let n = read_full(r, buffer)?;
+        if n == 0 {
+            return Ok(tail);
+        }
+        tail.add(&buffer[..n]);
+    }
+}
+
+/// Read until `buffer` is full or the source ends, returning the number of bytes read.
+///
+/// Pipes and network filesystems may return fewer bytes than requested well
+/// before the end.
+pub(crate) fn read_full(r: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buffer.len() {
+        match r.read(&mut buffer[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Whether `v1` and `v2` differ by exactly one bit.
+pub fn is_bitflipped(v1: u64, v2: u64) -> bool {
+    let v = v1 ^ v2;
+    if v == 0 {
+        return false;
+    }
+    v & (v - 1) == 0
+}
@@ -0,0 +1,92 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::Difference;
+
+/// Differing bits of a comparison, summed over blocks of a fixed number of bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heatmap {
+    /// Offset of the first byte of the first block.
+    start: u64,
+    block_size: u64,
+    blocks: Vec<u64>,
+}
+
+impl Heatmap {
+    /// Blocks of `block_size` bytes covering `len` bytes from `start`, the
+    /// offset of the first compared byte.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0.
+    pub fn new(start: u64, len: u64, block_size: u64) -> Self {
+        assert!(block_size > 0, "blocks cannot be empty");
+        Self {
+            start,
+            block_size,
+            blocks: vec![0; len.div_ceil(block_size) as usize],
+        }
+    }
+
+    /// Account the bits of a difference to the block of its offset.
+    pub fn add(&mut self, difference: &Difference) {
+        let i = ((difference.offset - self.start) / self.block_size) as usize;
+        if i >= self.blocks.len() {
+            self.blocks.resize(i + 1, 0);
+        }
+        self.blocks[i] += difference.xor().count_ones() as u64;
+    }
+
+    /// Add the bits of another heatmap over blocks of the same size and start.
+    pub fn merge(&mut self, other: &Heatmap) {
+        if other.blocks.len() > self.blocks.len() {
+            self.blocks.resize(other.blocks.len(), 0);
+        }
+        for (total, n) in self.blocks.iter_mut().zip(&other.blocks) {
+            *total += n;
+        }
+    }
+
+    /// Grow to cover `len` bytes from the start, for blocks past the last
+    /// difference when the length was not known in advance.
+    pub fn cover(&mut self, len: u64) {
+        let blocks = len.div_ceil(self.block_size) as usize;
+        if blocks > self.blocks.len() {
+            self.blocks.resize(blocks, 0);
+        }
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Number of differing bits in each block.
+    pub fn blocks(&self) -> &[u64] {
+        &self.blocks
+    }
+
+    /// Offset of the first byte of block `i`.
+    pub fn offset(&self, i: usize) -> u64 {
+        self.start + i as u64 * self.block_size
+    }
+
+    /// Most differing bits of any block.
+    pub fn max(&self) -> u64 {
+        self.blocks.iter().copied().max().unwrap_or_default()
+    }
+}
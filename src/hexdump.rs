@@ -0,0 +1,243 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::VecDeque, io::Write};
+
+/// Number of bytes of each file per row.
+pub const ROW_SIZE: usize = 16;
+
+/// How the bytes of a row are written, given whether they differ.
+pub struct Markup {
+    /// Hex of a byte.
+    pub hex: fn(u8, bool) -> String,
+    /// A printable ASCII character, or `.`.
+    pub ascii: fn(char, bool) -> String,
+}
+
+/// A `*` before the differing bytes, which are not marked in ASCII.
+const TEXT: Markup = Markup {
+    hex: |byte, differs| format!("{}{:02x}", if differs { '*' } else { ' ' }, byte),
+    ascii: |c, _| c.to_string(),
+};
+
+/// A row of both files, at the same offset of each.
+struct Row {
+    offset: u64,
+    left: Vec<u8>,
+    right: Vec<u8>,
+}
+
+/// Side-by-side hexdump of the rows which differ, with `context` equal rows
+/// around them, as `diff -U` does for text.
+///
+/// Each row shows the offset, hex and ASCII of both files, with a `*` before
+/// the differing bytes. Rows which are not adjacent are separated by `--`.
+pub struct Hexdump<W: Write> {
+    w: W,
+    context: usize,
+    /// Offsets of the first byte of each file.
+    skip1: u64,
+    skip2: u64,
+    /// Equal rows since the last printed one, up to `context` of them.
+    before: VecDeque<Row>,
+    /// Number of equal rows still to print after a differing one.
+    after: usize,
+    /// Offset following the last printed row.
+    printed: Option<u64>,
+}
+
+impl<W: Write> Hexdump<W> {
+    pub fn new(w: W, context: usize, skip1: u64, skip2: u64) -> Self {
+        Self {
+            w,
+            context,
+            skip1,
+            skip2,
+            before: VecDeque::with_capacity(context),
+            after: 0,
+            printed: None,
+        }
+    }
+
+    /// Account the next row of both files, returning the number of differing bytes.
+    pub fn row(&mut self, offset: u64, left: &[u8], right: &[u8]) -> eyre::Result<usize> {
+        let differences = left.iter().zip(right).filter(|(l, r)| l != r).count();
+        if differences == 0 && self.after == 0 {
+            if self.context > 0 {
+                self.keep(offset, left, right);
+            }
+            return Ok(0);
+        }
+
+        while let Some(row) = self.before.pop_front() {
+            self.print(row.offset, &row.left, &row.right)?;
+        }
+        self.print(offset, left, right)?;
+        self.after = match differences {
+            0 => self.after - 1,
+            _ => self.context,
+        };
+        Ok(differences)
+    }
+
+    /// Keep an equal row, in case one of the next ones differs.
+    fn keep(&mut self, offset: u64, left: &[u8], right: &[u8]) {
+        // Reuse the buffers of the oldest row once there are enough of them.
+        let mut row = match self.before.len() == self.context {
+            true => self.before.pop_front().unwrap(),
+            false => Row {
+                offset,
+                left: Vec::with_capacity(ROW_SIZE),
+                right: Vec::with_capacity(ROW_SIZE),
+            },
+        };
+        row.offset = offset;
+        row.left.clear();
+        row.left.extend_from_slice(left);
+        row.right.clear();
+        row.right.extend_from_slice(right);
+        self.before.push_back(row);
+    }
+
+    /// Whether equal rows following a differing one are still to be printed.
+    pub fn pending(&self) -> bool {
+        self.after > 0
+    }
+
+    pub fn finish(&mut self) -> eyre::Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+
+    fn print(&mut self, offset: u64, left: &[u8], right: &[u8]) -> eyre::Result<()> {
+        if self.printed.is_some_and(|printed| printed != offset) {
+            writeln!(self.w, "--")?;
+        }
+        self.printed = Some(offset + left.len() as u64);
+
+        writeln!(
+            self.w,
+            "{:08x} {}  |{}|   {:08x} {}  |{}|",
+            offset,
+            hex(left, right, &TEXT),
+            ascii(left, right, &TEXT),
+            offset - self.skip1 + self.skip2,
+            hex(right, left, &TEXT),
+            ascii(right, left, &TEXT),
+        )?;
+        Ok(())
+    }
+}
+
+/// Hex of `bytes`, padded to a full row, with those which differ from `other` marked.
+pub fn hex(bytes: &[u8], other: &[u8], markup: &Markup) -> String {
+    let mut s = String::with_capacity(ROW_SIZE * 3);
+    for (i, byte) in bytes.iter().enumerate() {
+        s += &(markup.hex)(*byte, other.get(i) != Some(byte));
+    }
+    s + &"   ".repeat(ROW_SIZE - bytes.len())
+}
+
+/// Printable ASCII characters of `bytes`, padded to a full row, with a `.`
+/// for the others and those which differ from `other` marked.
+pub fn ascii(bytes: &[u8], other: &[u8], markup: &Markup) -> String {
+    let mut s = String::with_capacity(ROW_SIZE);
+    for (i, &byte) in bytes.iter().enumerate() {
+        let c = match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        };
+        s += &(markup.ascii)(c, other.get(i) != Some(&byte));
+    }
+    s + &" ".repeat(ROW_SIZE - bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dump of `rows` rows of 16 bytes, differing in those of `differing`,
+    /// as offsets of the rows printed and `--` separators.
+    fn dump(context: usize, rows: u64, differing: &[u64]) -> Vec<String> {
+        let mut w = Vec::new();
+        let mut dump = Hexdump::new(&mut w, context, 0, 0);
+        let left = [b'a'; ROW_SIZE];
+        for row in 0..rows {
+            let mut right = left;
+            if differing.contains(&row) {
+                right[3] = b'b';
+            }
+            dump.row(row * ROW_SIZE as u64, &left, &right).unwrap();
+        }
+        dump.finish().unwrap();
+        drop(dump);
+
+        String::from_utf8(w)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(' ').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn context() {
+        assert_eq!(
+            dump(1, 8, &[1, 5]),
+            ["00000000", "00000010", "00000020", "--", "00000040", "00000050", "00000060"]
+        );
+        assert_eq!(
+            dump(2, 8, &[1, 5]),
+            [
+                "00000000", "00000010", "00000020", "00000030", "00000040", "00000050", "00000060",
+                "00000070"
+            ]
+        );
+        // Context extends from the last differing row.
+        assert_eq!(
+            dump(1, 8, &[1, 2]),
+            ["00000000", "00000010", "00000020", "00000030"]
+        );
+    }
+
+    #[test]
+    fn no_context() {
+        assert_eq!(dump(0, 8, &[1, 5]), ["00000010", "--", "00000050"]);
+        assert_eq!(
+            dump(0, 8, &[1, 2, 7]),
+            ["00000010", "00000020", "--", "00000070"]
+        );
+        assert!(dump(0, 8, &[]).is_empty());
+        assert!(dump(3, 8, &[]).is_empty());
+    }
+
+    #[test]
+    fn row() {
+        let mut w = Vec::new();
+        let mut dump = Hexdump::new(&mut w, 0, 0x100, 0x10);
+        let left = b"0123456789ab\x00\x7f";
+        let right = b"0123x56789ab\x00\x80";
+        assert_eq!(dump.row(0x120, left, right).unwrap(), 2);
+        drop(dump);
+
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "00000120  30 31 32 33*34 35 36 37 38 39 61 62 00*7f        |0123456789ab..  |   \
+             00000030  30 31 32 33*78 35 36 37 38 39 61 62 00*80        |0123x56789ab..  |\n"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serde::Serialize;
+
+use crate::{Difference, Endian};
+
+/// Number of flips per bit position within a word, by direction.
+///
+/// With little endian words, bit 8 of a 16-bit word is bit 0 of its second
+/// byte; with big endian words it is bit 0 of its first byte.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    /// Bits 0 in the first source and 1 in the second one, indexed by position.
+    pub set: Vec<u64>,
+    /// Bits 1 in the first source and 0 in the second one, indexed by position.
+    pub cleared: Vec<u64>,
+    #[serde(skip)]
+    endian: Endian,
+}
+
+impl Histogram {
+    /// Histogram over words of `word_size` bytes.
+    pub fn new(word_size: usize, endian: Endian) -> Self {
+        let bits = word_size * u8::BITS as usize;
+        Self {
+            set: vec![0; bits],
+            cleared: vec![0; bits],
+            endian,
+        }
+    }
+
+    /// Size of the words in bytes.
+    pub fn word_size(&self) -> usize {
+        self.set.len() / u8::BITS as usize
+    }
+
+    pub fn add(&mut self, difference: &Difference) {
+        let word_size = self.word_size();
+        let byte_bits = u8::BITS as usize;
+
+        for bit in difference.flipped_bits() {
+            let bit = bit as usize;
+
+            // Locate the byte of the flipped bit, then its significance within
+            // the histogram word.
+            let offset = difference.offset as usize
+                + self.endian.byte_offset(bit / byte_bits, difference.size);
+            let significance = self.endian.byte_offset(offset % word_size, word_size);
+            let position = significance * byte_bits + bit % byte_bits;
+
+            if difference.right & (1 << bit) != 0 {
+                self.set[position] += 1;
+            } else {
+                self.cleared[position] += 1;
+            }
+        }
+    }
+
+    /// Add the flips of another histogram over words of the same size.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (total, n) in self.set.iter_mut().zip(&other.set) {
+            *total += n;
+        }
+        for (total, n) in self.cleared.iter_mut().zip(&other.cleared) {
+            *total += n;
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(1, Endian::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn difference(offset: u64, size: usize, left: u64, right: u64) -> Difference {
+        Difference {
+            offset,
+            size,
+            left,
+            right,
+            ignored: 0,
+        }
+    }
+
+    /// Positions of the bits set and cleared, in ascending order.
+    fn positions(histogram: &Histogram) -> (Vec<usize>, Vec<usize>) {
+        let flipped = |counts: &[u64]| {
+            counts
+                .iter()
+                .enumerate()
+                .flat_map(|(position, &n)| std::iter::repeat_n(position, n as usize))
+                .collect()
+        };
+        (flipped(&histogram.set), flipped(&histogram.cleared))
+    }
+
+    #[test]
+    fn bytes() {
+        let mut histogram = Histogram::default();
+        histogram.add(&difference(5, 1, 0b0000_0001, 0b1000_0000));
+        histogram.add(&difference(6, 1, 0b0000_0000, 0b1000_0010));
+
+        assert_eq!(positions(&histogram), (vec![1, 7, 7], vec![0]));
+    }
+
+    #[test]
+    fn little_endian() {
+        let mut histogram = Histogram::new(2, Endian::Little);
+        // Bit 0 of the second byte of a word is bit 8 of the word.
+        histogram.add(&difference(1, 1, 0, 0x01));
+        histogram.add(&difference(2, 1, 0x80, 0));
+        histogram.add(&difference(0x101, 1, 0, 0x04));
+
+        assert_eq!(positions(&histogram), (vec![8, 10], vec![7]));
+    }
+
+    #[test]
+    fn big_endian() {
+        let mut histogram = Histogram::new(2, Endian::Big);
+        // Bit 0 of the first byte of a word is bit 8 of the word.
+        histogram.add(&difference(0, 1, 0, 0x01));
+        histogram.add(&difference(3, 1, 0x80, 0));
+
+        assert_eq!(positions(&histogram), (vec![8], vec![7]));
+    }
+
+    #[test]
+    fn words() {
+        // Bit 8 of a 16-bit word stays bit 8 whatever the byte order, and
+        // lands on the matching byte of a wider histogram word.
+        for endian in [Endian::Little, Endian::Big] {
+            let mut histogram = Histogram::new(2, endian);
+            histogram.add(&difference(0, 2, 0, 0x0100));
+            assert_eq!(positions(&histogram), (vec![8], vec![]), "{:?}", endian);
+        }
+
+        let mut histogram = Histogram::new(4, Endian::Little);
+        histogram.add(&difference(2, 2, 0x0001, 0x8000));
+        assert_eq!(positions(&histogram), (vec![31], vec![16]));
+
+        let mut histogram = Histogram::new(4, Endian::Big);
+        histogram.add(&difference(2, 2, 0x0001, 0x8000));
+        assert_eq!(positions(&histogram), (vec![15], vec![0]));
+    }
+
+    #[test]
+    fn ignored() {
+        let mut histogram = Histogram::default();
+        histogram.add(&Difference {
+            ignored: 0x0f,
+            ..difference(0, 1, 0x00, 0xff)
+        });
+
+        assert_eq!(positions(&histogram), ((4..8).collect(), vec![]));
+    }
+
+    #[test]
+    fn merge() {
+        let mut histogram = Histogram::new(2, Endian::Little);
+        histogram.add(&difference(0, 1, 0, 1));
+        let mut other = Histogram::new(2, Endian::Little);
+        other.add(&difference(1, 1, 0, 1));
+        other.add(&difference(2, 1, 1, 0));
+        histogram.merge(&other);
+
+        assert_eq!(positions(&histogram), (vec![0, 8], vec![0]));
+    }
+}
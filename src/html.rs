@@ -0,0 +1,194 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Write;
+
+use bincmp::{DiffRange, Heatmap};
+
+use crate::{
+    hexdump::{ascii, hex, Markup, ROW_SIZE},
+    output::{Offsets, Summary, ValueOutputFormat},
+};
+
+/// Number of cells of the heatmap.
+pub const HEATMAP_CELLS: u64 = 1024;
+
+/// Number of cells per row of the heatmap.
+const HEATMAP_COLUMNS: usize = 64;
+
+/// Size in pixels of a cell of the heatmap.
+const CELL_SIZE: usize = 10;
+
+/// Rows of a range shown at most, beyond which its dump is cut.
+const MAX_ROWS: usize = 64;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table.stats td { padding: 0 1em 0 0; }
+pre { font-size: 0.9em; }
+.d { color: #c00; font-weight: bold; }
+summary { cursor: pointer; font-family: monospace; }
+";
+
+/// The differing bytes in a span of the `d` class, escaping the ASCII.
+const HTML: Markup = Markup {
+    hex: |byte, differs| match differs {
+        false => format!(" {:02x}", byte),
+        true => format!(" <span class=\"d\">{:02x}</span>", byte),
+    },
+    ascii: |c, differs| {
+        let c = escape(&c.to_string());
+        match differs {
+            false => c,
+            true => format!("<span class=\"d\">{}</span>", c),
+        }
+    },
+};
+
+/// A static HTML page, with no external assets, reporting a comparison of
+/// two files held in memory.
+pub struct Report<'a> {
+    pub names: [&'a str; 2],
+    pub data: [&'a [u8]; 2],
+    /// Offset of the first byte of each file.
+    pub skips: [u64; 2],
+    pub offsets: Offsets,
+}
+
+impl Report<'_> {
+    pub fn write(
+        &self,
+        mut w: impl Write,
+        summary: &Summary,
+        heatmap: &Heatmap,
+        ranges: &[DiffRange],
+    ) -> eyre::Result<()> {
+        let [name1, name2] = self.names.map(escape);
+        writeln!(w, "<!DOCTYPE html>")?;
+        writeln!(w, "<html><head><meta charset=\"utf-8\">")?;
+        writeln!(w, "<title>bincmp {} {}</title>", name1, name2)?;
+        writeln!(w, "<style>{}</style></head><body>", STYLE)?;
+        writeln!(w, "<h1>{} vs {}</h1>", name1, name2)?;
+
+        writeln!(w, "<h2>Statistics</h2><table class=\"stats\">")?;
+        for (label, value) in summary.report(&ValueOutputFormat::Hex, &self.offsets) {
+            writeln!(w, "<tr><td>{}</td><td>{}</td></tr>", label, escape(&value))?;
+        }
+        writeln!(w, "</table>")?;
+
+        writeln!(w, "<h2>Heatmap</h2>")?;
+        self.heatmap(&mut w, heatmap)?;
+
+        writeln!(w, "<h2>Ranges</h2>")?;
+        for range in ranges {
+            self.range(&mut w, range)?;
+        }
+
+        writeln!(w, "</body></html>")?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// An SVG grid of the blocks, shaded by their number of differing bits.
+    fn heatmap(&self, w: &mut impl Write, heatmap: &Heatmap) -> eyre::Result<()> {
+        let blocks = heatmap.blocks();
+        let rows = blocks.len().div_ceil(HEATMAP_COLUMNS);
+        let max = heatmap.max().max(1);
+        writeln!(
+            w,
+            "<p>{} bytes per cell, darker cells have more differing bits, up to {}.</p>",
+            heatmap.block_size(),
+            max
+        )?;
+        writeln!(
+            w,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            HEATMAP_COLUMNS * CELL_SIZE,
+            rows * CELL_SIZE
+        )?;
+        for (i, &bits) in blocks.iter().enumerate() {
+            // From white to red.
+            let shade = 255 - (bits * 255 / max) as u8;
+            let start = heatmap.offset(i);
+            writeln!(
+                w,
+                "<rect x=\"{}\" y=\"{}\" width=\"{size}\" height=\"{size}\" fill=\"#ff{:02x}{:02x}\" stroke=\"#eee\">\
+                 <title>0x{:x}..0x{:x}: {} bits</title></rect>",
+                i % HEATMAP_COLUMNS * CELL_SIZE,
+                i / HEATMAP_COLUMNS * CELL_SIZE,
+                shade,
+                shade,
+                start,
+                start + heatmap.block_size(),
+                bits,
+                size = CELL_SIZE,
+            )?;
+        }
+        writeln!(w, "</svg>")?;
+        Ok(())
+    }
+
+    /// A collapsible side-by-side hexdump of a range, with a row of context around it.
+    fn range(&self, w: &mut impl Write, range: &DiffRange) -> eyre::Result<()> {
+        let [skip1, skip2] = self.skips;
+        let (start, end) = ((range.start - skip1) as usize, (range.end - skip1) as usize);
+        // Context rows stop at the end of the longer file.
+        let len = self.data[0].len().max(self.data[1].len());
+        let first = (start / ROW_SIZE).saturating_sub(1);
+        let last = (end.div_ceil(ROW_SIZE) + 1).min(len.div_ceil(ROW_SIZE));
+
+        writeln!(
+            w,
+            "<details><summary>0x{:x}..0x{:x}: {} bytes, {} differences, {} bits</summary><pre>",
+            range.start,
+            range.end,
+            range.len(),
+            range.differences,
+            range.flipped_bits
+        )?;
+        for row in (first..last).take(MAX_ROWS) {
+            let pos = row * ROW_SIZE;
+            let [left, right] = self.data.map(|data| {
+                data.get(pos..(pos + ROW_SIZE).min(data.len()))
+                    .unwrap_or_default()
+            });
+            writeln!(
+                w,
+                "{:08x} {} {}   {:08x} {} {}",
+                skip1 + pos as u64,
+                hex(left, right, &HTML),
+                ascii(left, right, &HTML),
+                skip2 + pos as u64,
+                hex(right, left, &HTML),
+                ascii(right, left, &HTML),
+            )?;
+        }
+        if last - first > MAX_ROWS {
+            writeln!(w, "... {} more rows", last - first - MAX_ROWS)?;
+        }
+        writeln!(w, "</pre></details>")?;
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,85 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use bincmp::Heatmap;
+
+/// Pixels past the last block.
+const PADDING: [u8; 3] = [0x40, 0x40, 0x40];
+
+/// Write `heatmap` to `path` as an image `width` pixels wide, one pixel per
+/// block, in the format of its extension: `.png` or `.ppm`.
+pub fn write(path: &str, heatmap: &Heatmap, width: usize) -> eyre::Result<()> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let png = match extension.as_deref() {
+        Some("png") => true,
+        Some("ppm") => false,
+        _ => eyre::bail!("Unknown image format of {}, expected .png or .ppm", path),
+    };
+
+    let height = heatmap.blocks().len().div_ceil(width).max(1);
+    let pixels = pixels(heatmap, width * height);
+    let file = File::create(path).map_err(|e| eyre::eyre!("Cannot create {}: {}", path, e))?;
+    let mut w = BufWriter::new(file);
+    if png {
+        let mut encoder = png::Encoder::new(&mut w, width.try_into()?, height.try_into()?);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&pixels)?;
+    } else {
+        write!(w, "P6\n{} {}\n255\n", width, height)?;
+        w.write_all(&pixels)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// RGB of `count` pixels, those past the last block being padding.
+fn pixels(heatmap: &Heatmap, count: usize) -> Vec<u8> {
+    let max = heatmap.max();
+    let mut pixels = Vec::with_capacity(count * 3);
+    for i in 0..count {
+        match heatmap.blocks().get(i) {
+            Some(&bits) => pixels.extend_from_slice(&color(bits, max)),
+            None => pixels.extend_from_slice(&PADDING),
+        }
+    }
+    pixels
+}
+
+/// Black for blocks without differences, then from dark red to yellow as the
+/// number of differing bits reaches the most of any block.
+fn color(bits: u64, max: u64) -> [u8; 3] {
+    if bits == 0 {
+        return [0, 0, 0];
+    }
+    // In 1..=510, so that a single bit stands out of the black.
+    let heat = (bits * 509 / max) as usize + 1;
+    match heat {
+        0..=255 => [heat.max(0x80) as u8, 0, 0],
+        _ => [0xff, (heat - 255) as u8, 0],
+    }
+}
@@ -0,0 +1,136 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Stdin},
+};
+
+use bincmp::Pattern;
+use memmap2::{Mmap, MmapOptions};
+
+/// A file to compare: a regular file, a device, a FIFO, the standard input for
+/// `-`, or a pattern.
+pub struct Input {
+    source: Source,
+    regular: bool,
+    /// Size in bytes, unless only known once read.
+    pub size: Option<u64>,
+}
+
+enum Source {
+    File(File),
+    Stdin(Stdin),
+    Pattern(Pattern),
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::File(f) => f.read(buf),
+            Source::Stdin(stdin) => stdin.read(buf),
+            Source::Pattern(pattern) => pattern.read(buf),
+        }
+    }
+}
+
+impl Input {
+    pub fn open(path: &str) -> eyre::Result<Self> {
+        if path == "-" {
+            return Ok(Self {
+                source: Source::Stdin(io::stdin()),
+                regular: false,
+                size: None,
+            });
+        }
+
+        let mut f = File::open(path).map_err(|e| eyre::eyre!("Cannot open {}: {}", path, e))?;
+        let metadata = f.metadata()?;
+        let regular = metadata.is_file();
+        let size = if regular {
+            Some(metadata.len())
+        } else {
+            // Block devices and MTD character devices report their size when
+            // seeking to their end. FIFOs cannot seek, and other character
+            // devices report 0.
+            let size = f.seek(SeekFrom::End(0)).ok().filter(|&size| size > 0);
+            if size.is_some() {
+                f.rewind()?;
+            }
+            size
+        };
+
+        Ok(Self {
+            source: Source::File(f),
+            regular,
+            size,
+        })
+    }
+
+    /// Endless repetition of the pattern.
+    pub fn pattern(pattern: Pattern) -> Self {
+        Self {
+            source: Source::Pattern(pattern),
+            regular: false,
+            size: None,
+        }
+    }
+
+    /// Map the input from `skip`, limited to `length` bytes, if it is a regular file.
+    pub fn map(&self, skip: u64, length: Option<u64>) -> eyre::Result<Option<Mmap>> {
+        let (Source::File(f), true, Some(size)) = (&self.source, self.regular, self.size) else {
+            return Ok(None);
+        };
+
+        let len = size.saturating_sub(skip).min(length.unwrap_or(u64::MAX));
+        // SAFETY: the files are expected not to change during the comparison.
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(skip)
+                .len(len.try_into()?)
+                .map(f)?
+        };
+        Ok(Some(map))
+    }
+
+    /// The whole input from `skip`, limited to `length` bytes, memory-mapped
+    /// if it is a regular file.
+    pub fn load(self, skip: u64, length: Option<u64>) -> eyre::Result<Box<dyn AsRef<[u8]>>> {
+        if let Some(map) = self.map(skip, length)? {
+            return Ok(Box::new(map));
+        }
+        let mut data = Vec::new();
+        self.stream(skip, length)?.read_to_end(&mut data)?;
+        Ok(Box::new(data))
+    }
+
+    /// Read the input from `skip`, limited to `length` bytes.
+    ///
+    /// Inputs which cannot seek are read up to `skip`.
+    pub fn stream(mut self, skip: u64, length: Option<u64>) -> eyre::Result<impl Read> {
+        let seeked = match &mut self.source {
+            Source::File(f) => f.seek(SeekFrom::Start(skip)).is_ok(),
+            Source::Stdin(_) | Source::Pattern(_) => false,
+        };
+        if !seeked {
+            io::copy(&mut (&mut self.source).take(skip), &mut io::sink())?;
+        }
+
+        Ok(self.source.take(length.unwrap_or(u64::MAX)))
+    }
+}
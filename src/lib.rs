@@ -0,0 +1,65 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Library interface for analyzing differences between binaries.
+//!
+//! The [`Differ`] compares two [`Read`](std::io::Read) sources and yields
+//! every [`Difference`] found, in offset order. Adjacent differences can be
+//! grouped into [`DiffRange`]s with [`Differ::ranges`]. Either way, the
+//! [`Stats`] of the comparison are available once it is done. Sources held
+//! in memory, such as memory-mapped files, can be compared in parallel with
+//! [`Differ::parallel`]. [`MultiDiffer`] compares several sources against a
+//! common reference, and [`Vote`] reconstructs data read several times by
+//! a majority vote of each bit. [`Merge`] tells how two sources changed
+//! from a common base, and whether their changes conflict.
+//!
+//! When bytes may have been inserted or deleted, [`align`] finds the spans
+//! which differ once both sources are aligned, and [`Resync`] does so for
+//! sources too large to be held in memory.
+
+mod align;
+mod differ;
+mod heatmap;
+mod histogram;
+mod mask;
+mod merge;
+mod multi;
+mod parallel;
+mod pattern;
+mod ranges;
+mod resync;
+mod stats;
+mod tail;
+mod vote;
+mod word;
+
+pub use align::{align, align_deadline, Edit, EditKind, ALIGN_TIMEOUT};
+pub use differ::{is_bitflipped, Differ, Difference, BUFFER_SIZE, MAX_WORD_SIZE};
+pub use heatmap::Heatmap;
+pub use histogram::Histogram;
+pub use mask::{parse_hex, parse_number, Mask, ParseMaskError};
+pub use merge::{Merge, MergeKind, MergeRange, MergeStats};
+pub use multi::{MultiDiffer, MultiDifference};
+pub use parallel::{ParDiffer, ParRanges, CHUNK_SIZE};
+pub use pattern::{ParsePatternError, Pattern};
+pub use ranges::{DiffRange, Ranges, PREVIEW_SIZE};
+pub use resync::{Resync, ANCHOR_SIZE};
+pub use stats::Stats;
+pub use tail::Tail;
+pub use vote::{UnstableByte, Vote, VoteStats};
+pub use word::Endian;
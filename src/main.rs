@@ -16,136 +16,861 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+mod hexdump;
+mod html;
+mod image;
+mod input;
+mod output;
+mod staged;
+mod tui;
+mod value;
+
 use std::{
-    fs::File,
-    io::{stdout, Read, Write},
+    cmp::Ordering,
+    io::{self, stdout, BufReader, BufWriter, Read, Write},
+    process::ExitCode,
 };
 
-use clap::{Parser, ValueEnum};
-use tabwriter::TabWriter;
-
-#[derive(ValueEnum, Clone, Debug)]
-enum ValueOutputFormat {
-    Hex,
-    Decimal,
-    Binary,
-    Combined,
-}
+use bincmp::{
+    parse_hex, parse_number, DiffRange, Differ, Difference, Edit, Mask, Merge, MultiDiffer,
+    Pattern, Resync, Stats, Tail, Vote,
+};
+use clap::{builder::TypedValueParser, Parser, ValueEnum};
+use hexdump::{Hexdump, ROW_SIZE};
+use html::{Report, HEATMAP_CELLS};
+use input::Input;
+use output::{EditCounts, FileSummary, Offsets, Output, OutputFormat, Summary, ValueOutputFormat};
+use staged::Staged;
+use tui::Browser;
+use value::ValueType;
 
 /// Compare binary files
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[arg()]
+    /// First file, or - for the standard input
     file1: String,
 
-    #[arg()]
-    file2: String,
+    #[arg(required_unless_present = "against_pattern")]
+    /// Second file, or - for the standard input
+    file2: Option<String>,
+
+    #[arg(
+        conflicts_with_all = ["against_pattern", "ranges", "align", "resync", "tail", "pad", "value_type", "histogram"]
+    )]
+    /// Further files to compare against the first one
+    others: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "HEX",
+        value_parser = Pattern::parse,
+        conflicts_with_all = ["file2", "skip2", "align", "resync", "tail", "pad"]
+    )]
+    /// Compare the first file against this repeated pattern instead, e.g. ff or 55aa
+    against_pattern: Option<Pattern>,
+
+    #[arg(long, default_value = "0", value_name = "OFFSET", value_parser = parse_number)]
+    /// Start comparing the first file at this offset
+    skip1: u64,
+
+    #[arg(long, default_value = "0", value_name = "OFFSET", value_parser = parse_number)]
+    /// Start comparing the second file at this offset
+    skip2: u64,
+
+    #[arg(short = 'n', long, value_parser = parse_number)]
+    /// Compare at most this many bytes
+    length: Option<u64>,
+
+    #[arg(short, long, value_name = "FILE")]
+    /// Exclude the offset ranges, or bits thereof, listed in this file
+    mask: Option<String>,
 
     #[arg(short, long, default_value = "hex")]
+    /// Format of the values in table output
     format: ValueOutputFormat,
 
+    #[arg(short, long, default_value = "table")]
+    /// Output format
+    output: OutputFormat,
+
     #[arg(short, long)]
     /// Search only for a single bit flip
     single_bitflip_only: bool,
+
+    #[arg(short, long, default_value = "8", value_name = "BITS", value_parser = word_bits_parser())]
+    /// Compare aligned words of this width
+    word_size: usize,
+
+    #[arg(short, long, default_value = "little")]
+    /// Byte order of the words
+    endian: Endian,
+
+    #[arg(long = "as", value_name = "TYPE", conflicts_with = "word_size")]
+    /// Compare words of this type, printing their values and delta
+    value_type: Option<ValueType>,
+
+    #[arg(short, long)]
+    /// Group consecutive differences into start..end (exclusive) ranges
+    ranges: bool,
+
+    #[arg(short, long, default_value = "0", requires = "ranges")]
+    /// Maximum number of equal bytes between differences of the same range
+    gap: u64,
+
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["ranges", "histogram", "mask", "word_size", "value_type", "single_bitflip_only"]
+    )]
+    /// Align the files, which are read into memory, to find inserted and deleted bytes
+    align: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["align", "ranges", "histogram", "mask", "word_size", "value_type", "single_bitflip_only"]
+    )]
+    /// Compare the files as streams, searching for the shift after a run of differences
+    resync: bool,
+
+    #[arg(long, default_value = "0x100000", value_name = "BYTES", value_parser = parse_number, requires = "resync")]
+    /// Bytes searched ahead in each file to resynchronise
+    resync_window: u64,
+
+    #[arg(long, default_value = "32", value_name = "COUNT", requires = "resync")]
+    /// Number of differences after which the files are considered shifted
+    resync_threshold: u64,
+
+    #[arg(long, conflicts_with_all = ["pad", "align", "resync"])]
+    /// Report the bytes of the longer file past the end of the shorter one
+    tail: bool,
+
+    #[arg(long, value_name = "BYTE", value_parser = parse_byte, conflicts_with_all = ["align", "resync"])]
+    /// Compare the shorter file as if padded with this byte, e.g. ff
+    pad: Option<u8>,
+
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        requires = "others",
+        conflicts_with_all = ["skip2", "ranges", "align", "resync", "tail", "pad", "mask", "word_size", "value_type", "single_bitflip_only", "histogram"]
+    )]
+    /// Reconstruct three or more reads of the same data into IMAGE by a majority vote of each bit
+    vote: Option<String>,
+
+    #[arg(
+        long,
+        requires = "others",
+        conflicts_with_all = ["skip2", "ranges", "align", "resync", "tail", "pad", "mask", "word_size", "value_type", "single_bitflip_only", "histogram", "vote"]
+    )]
+    /// Compare the second and third files, as ours and theirs, against the first one as their common base
+    three_way: bool,
+
+    #[arg(long, value_name = "FILE", requires = "three_way")]
+    /// Write the merge of ours and theirs to FILE, unless they conflict
+    merged: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["others", "output", "ranges", "align", "resync", "tail", "pad", "mask", "word_size", "value_type", "single_bitflip_only", "stats", "histogram"]
+    )]
+    /// Print a side-by-side hexdump of the rows which differ
+    hexdump: bool,
+
+    #[arg(
+        short = 'U',
+        long,
+        default_value = "3",
+        value_name = "ROWS",
+        requires = "hexdump"
+    )]
+    /// Number of equal rows printed around those which differ
+    context: usize,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["others", "against_pattern", "output", "ranges", "align", "resync", "tail", "pad", "mask", "value_type", "single_bitflip_only", "stats", "histogram", "hexdump", "quiet", "max_diffs"]
+    )]
+    /// Browse the files interactively, as synchronized hex panes
+    tui: bool,
+
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        conflicts_with_all = ["others", "ranges", "align", "resync", "hexdump", "tui"]
+    )]
+    /// Draw where the files differ into IMAGE, a .png or .ppm with a pixel per block of bytes
+    heatmap: Option<String>,
+
+    #[arg(
+        long,
+        default_value = "256",
+        value_name = "PIXELS",
+        requires = "heatmap"
+    )]
+    /// Width of the heatmap
+    heatmap_width: usize,
+
+    #[arg(long, default_value = "512", value_name = "BYTES", value_parser = parse_number, requires = "heatmap")]
+    /// Bytes per pixel of the heatmap
+    heatmap_block: u64,
+
+    #[arg(long)]
+    /// Read regular files sequentially rather than memory-mapping and comparing them in parallel
+    no_mmap: bool,
+
+    #[arg(short, long)]
+    /// Print nothing and stop at the first difference, for the exit status only
+    quiet: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    /// Stop after reporting this many differences, ranges or edits
+    max_diffs: Option<usize>,
+
+    #[arg(long)]
+    /// Report only the statistics of the differences
+    stats: bool,
+
+    #[arg(long)]
+    /// Report only the number of flips per bit position
+    histogram: bool,
+
+    #[arg(long, value_name = "BITS", value_parser = word_bits_parser())]
+    /// Word width of the bit positions in the histogram [default: --word-size]
+    histogram_width: Option<usize>,
 }
 
-const BUFFER_SIZE: usize = 1024;
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl From<Endian> for bincmp::Endian {
+    fn from(endian: Endian) -> Self {
+        match endian {
+            Endian::Little => bincmp::Endian::Little,
+            Endian::Big => bincmp::Endian::Big,
+        }
+    }
+}
 
-fn main() -> eyre::Result<()> {
-    let args = Args::parse();
+impl Args {
+    /// Whether only aggregate reports are requested, rather than each difference.
+    fn report_only(&self) -> bool {
+        self.stats || self.histogram
+    }
 
-    let mut f1 = File::open(&args.file1)?;
-    let mut f2 = File::open(&args.file2)?;
+    /// Number of differences, ranges or edits after which to stop.
+    fn max_diffs(&self) -> usize {
+        match self.quiet {
+            true => 1,
+            false => self.max_diffs.unwrap_or(usize::MAX),
+        }
+    }
 
-    let mut buffer1 = [0u8; BUFFER_SIZE];
-    let mut buffer2 = [0u8; BUFFER_SIZE];
+    /// Whether several files are compared against the first one.
+    fn multi(&self) -> bool {
+        !self.others.is_empty()
+    }
 
-    let mut offset = 0;
-    let mut tw = TabWriter::new(stdout())
-        .padding(5)
-        .alignment(tabwriter::Alignment::Right);
+    /// Whether differences are reported as edits rather than offset by offset.
+    fn edits(&self) -> bool {
+        self.align || self.resync
+    }
+}
 
-    match &args.format {
-        ValueOutputFormat::Combined => writeln!(tw, "OFFSET\tHex\tFILE1\tHex\tFILE2\tHex\t")?,
-        _ => writeln!(tw, "OFFSET\tFILE1\tFILE2\t")?,
+/// Decimal, or hexadecimal with a `0x` prefix.
+fn parse_byte(s: &str) -> Result<u8, String> {
+    match parse_hex(s, "byte")?[..] {
+        [b] => Ok(b),
+        _ => Err(format!("{:?} is more than a byte", s)),
     }
+}
 
-    loop {
-        let n1 = f1.read(&mut buffer1)?;
-        let n2 = f2.read(&mut buffer2)?;
+fn word_bits_parser() -> impl TypedValueParser<Value = usize> {
+    clap::builder::PossibleValuesParser::new(["8", "16", "32", "64"])
+        .map(|bits| bits.parse::<usize>().unwrap())
+}
 
-        let n = std::cmp::min(n1, n2);
+/// Exit with 0 if the files are identical, 1 if they differ and 2 on errors, as `cmp` does.
+fn main() -> ExitCode {
+    match run(&Args::parse()) {
+        Ok(false) => ExitCode::SUCCESS,
+        Ok(true) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(2)
+        }
+    }
+}
 
-        if n != 0 {
-            compare_buffers(
-                &mut tw,
-                &buffer1[..n],
-                &buffer2[..n],
-                offset,
-                &args.format,
-                args.single_bitflip_only,
-            )?;
+/// Compare the files, returning whether they differ.
+fn run(args: &Args) -> eyre::Result<bool> {
+    let files = std::iter::once(&args.file1)
+        .chain(&args.file2)
+        .chain(&args.others);
+    if files.filter(|file| *file == "-").count() > 1 {
+        eyre::bail!("Only one of the files can be read from the standard input");
+    }
+    if let Some(image) = &args.vote {
+        return vote(args, image);
+    }
+    if args.three_way {
+        return merge(args);
+    }
+    if args.multi() {
+        return compare_multi(args);
+    }
+    let f1 = Input::open(&args.file1)?;
+    let f2 = match (&args.against_pattern, &args.file2) {
+        // In phase with the offsets of the first file.
+        (Some(pattern), _) => Input::pattern(pattern.clone().offset(args.skip1)),
+        (None, Some(file2)) => Input::open(file2)?,
+        (None, None) => unreachable!("required by clap"),
+    };
+    if args.hexdump {
+        return hexdump(args, f1, f2);
+    }
+    if args.tui {
+        return tui(args, f1, f2);
+    }
+    if args.heatmap.is_some() {
+        if args.heatmap_width == 0 || args.heatmap_block == 0 {
+            eyre::bail!("The heatmap width and block size cannot be 0");
+        }
+        if matches!(args.output, OutputFormat::Html) {
+            eyre::bail!("HTML reports have a heatmap of their own");
         }
+    }
+    if matches!(args.output, OutputFormat::Html) && !args.edits() {
+        return html(args, f1, f2);
+    }
+    let (file1_size, file2_size) = (f1.size, f2.size);
 
-        // EOF
-        if n < BUFFER_SIZE {
-            match n1.cmp(&n2) {
-                std::cmp::Ordering::Less => eprintln!(
-                    "NOTE: The second file ({}) is larger than the first file ({}).",
-                    args.file2, args.file1
-                ),
-                std::cmp::Ordering::Greater => eprintln!(
-                    "NOTE: The first file ({}) is larger than the second file ({}).",
-                    args.file1, args.file2
-                ),
-                std::cmp::Ordering::Equal => (),
-            };
-            break;
+    let mut out = output::new(writer(args), args)?;
+
+    let (summary, different) = if args.edits() {
+        let f1 = f1.stream(args.skip1, args.length)?;
+        let f2 = f2.stream(args.skip2, args.length)?;
+        let edits = if args.align {
+            align(args, f1, f2, out.as_mut())?
+        } else {
+            resync(args, f1, f2, out.as_mut())?
+        };
+        let different = edits.edits > 0;
+        (Summary::aligned(file1_size, file2_size, edits), different)
+    } else {
+        // Parallel comparisons run ahead of the differences reported, so
+        // that the statistics would not stop at the last one.
+        let maps = if args.no_mmap || args.max_diffs.is_some() {
+            None
+        } else {
+            f1.map(args.skip1, args.length)?
+                .zip(f2.map(args.skip2, args.length)?)
+        };
+        let mut compared = match maps {
+            Some((m1, m2)) => compare_parallel(args, &m1, &m2, out.as_mut())?,
+            None => {
+                let f1 = f1.stream(args.skip1, args.length)?;
+                let f2 = f2.stream(args.skip2, args.length)?;
+                compare(args, f1, f2, out.as_mut())?
+            }
+        };
+        if let Some(image) = &args.heatmap {
+            let mut heatmap = compared.stats.heatmap.take().expect("heatmap requested");
+            heatmap.cover(compared.stats.compared);
+            image::write(image, &heatmap, args.heatmap_width)?;
+        }
+        // Lengths do not matter once padded, nor against a pattern.
+        let lengths = args.pad.is_none() && args.against_pattern.is_none();
+        if lengths && !args.quiet && !args.tail {
+            let file2 = args.file2.as_deref().unwrap_or_default();
+            note_eof(&args.file1, file2, compared.eof_ordering);
         }
 
-        offset += BUFFER_SIZE;
+        let different = compared.stats.differences > 0
+            || (lengths && compared.eof_ordering.is_some_and(Ordering::is_ne));
+        let summary = Summary {
+            masked: args.mask.is_some(),
+            tail: compared.tail,
+            ..Summary::new(file1_size, file2_size, compared.stats, compared.ranges)
+        };
+        (summary, different)
+    };
+    if let Some(tail) = summary.tail.as_ref().filter(|_| !args.report_only()) {
+        out.tail(tail)?;
     }
+    out.finish(&summary)?;
 
-    tw.flush()?;
+    Ok(different)
+}
 
-    Ok(())
+/// Outcome of an offset by offset comparison.
+struct Compared {
+    stats: Stats,
+    /// Number of ranges, if grouping differences.
+    ranges: Option<u64>,
+    /// How the lengths of the files compare, unless stopped early.
+    eof_ordering: Option<Ordering>,
+    tail: Option<Tail>,
+}
+
+/// Compare both files offset by offset.
+fn compare(
+    args: &Args,
+    f1: impl Read,
+    f2: impl Read,
+    out: &mut dyn Output,
+) -> eyre::Result<Compared> {
+    let differ = differ(args, f1, f2)?;
+
+    if args.ranges {
+        let mut ranges = differ.ranges(args.gap);
+        let count = report_ranges(args, &mut ranges, out)?;
+        Ok(Compared {
+            stats: ranges.stats(),
+            ranges: Some(count),
+            eof_ordering: ranges.eof_ordering(),
+            tail: if args.tail { ranges.tail()? } else { None },
+        })
+    } else {
+        let mut differ = differ;
+        report_differences(args, &mut differ, out)?;
+        Ok(Compared {
+            stats: differ.stats(),
+            ranges: None,
+            eof_ordering: differ.eof_ordering(),
+            tail: if args.tail { differ.tail()? } else { None },
+        })
+    }
+}
+
+/// Same as [`compare`], for memory-mapped files compared in parallel.
+fn compare_parallel(
+    args: &Args,
+    m1: &[u8],
+    m2: &[u8],
+    out: &mut dyn Output,
+) -> eyre::Result<Compared> {
+    let differ = differ(args, m1, m2)?.parallel();
+
+    if args.ranges {
+        let mut ranges = differ.ranges(args.gap);
+        let count = report_ranges(args, &mut ranges, out)?;
+        Ok(Compared {
+            stats: ranges.stats(),
+            ranges: Some(count),
+            eof_ordering: ranges.eof_ordering(),
+            tail: ranges.tail().filter(|_| args.tail),
+        })
+    } else {
+        let mut differ = differ;
+        report_differences(args, &mut differ, out)?;
+        Ok(Compared {
+            stats: differ.stats(),
+            ranges: None,
+            eof_ordering: differ.eof_ordering(),
+            tail: differ.tail().filter(|_| args.tail),
+        })
+    }
+}
+
+/// Compare each of the other files against the first one, which is read once for each.
+fn compare_multi(args: &Args) -> eyre::Result<bool> {
+    // The first file is read again against each other file, which a pipe or
+    // the standard input would not allow.
+    let file1_size = Input::open(&args.file1)?.size;
+    if file1_size.is_none() {
+        eyre::bail!(
+            "The first file must be a regular file or a device when comparing several files"
+        );
+    }
+    let names: Vec<&String> = args.file2.iter().chain(&args.others).collect();
+
+    let mut sizes = Vec::new();
+    let mut differs = Vec::new();
+    for name in &names {
+        let f1 = Input::open(&args.file1)?.stream(args.skip1, args.length)?;
+        let f = Input::open(name)?;
+        sizes.push(f.size);
+        differs.push(differ(args, f1, f.stream(args.skip2, args.length)?)?);
+    }
+    let mut differ = MultiDiffer::new(differs);
+
+    let mut out = output::new(writer(args), args)?;
+    for difference in (&mut differ).take(args.max_diffs()) {
+        let difference = difference?;
+        if !args.report_only() {
+            out.multi(&difference)?;
+        }
+    }
+
+    let eof_orderings = differ.eof_orderings();
+    if !args.quiet {
+        for (name, eof_ordering) in names.iter().zip(&eof_orderings) {
+            note_eof(&args.file1, name, *eof_ordering);
+        }
+    }
+
+    let stats = differ.stats();
+    let different = stats.iter().any(|stats| stats.differences > 0)
+        || eof_orderings
+            .iter()
+            .flatten()
+            .any(|ordering| ordering.is_ne());
+    let files = names
+        .iter()
+        .zip(sizes)
+        .zip(stats)
+        .map(|((name, size), stats)| FileSummary::new(name, size, stats))
+        .collect();
+    let summary = Summary {
+        masked: args.mask.is_some(),
+        ..Summary::multi(file1_size, files)
+    };
+    out.finish(&summary)?;
+
+    Ok(different)
+}
+
+/// Print a side-by-side hexdump of both files, row by row.
+fn hexdump(args: &Args, f1: Input, f2: Input) -> eyre::Result<bool> {
+    let mut f1 = BufReader::new(f1.stream(args.skip1, args.length)?);
+    let mut f2 = BufReader::new(f2.stream(args.skip2, args.length)?);
+    let mut dump = Hexdump::new(writer(args), args.context, args.skip1, args.skip2);
+
+    let (mut left, mut right) = (Vec::with_capacity(ROW_SIZE), Vec::with_capacity(ROW_SIZE));
+    let mut offset = args.skip1;
+    let mut differences = 0;
+    let eof_ordering = loop {
+        if differences >= args.max_diffs() && !dump.pending() {
+            break None;
+        }
+        left.clear();
+        right.clear();
+        (&mut f1).take(ROW_SIZE as u64).read_to_end(&mut left)?;
+        (&mut f2).take(ROW_SIZE as u64).read_to_end(&mut right)?;
+
+        let n = std::cmp::min(left.len(), right.len());
+        if n > 0 {
+            differences += dump.row(offset, &left[..n], &right[..n])?;
+            offset += n as u64;
+        }
+        if n < ROW_SIZE {
+            break Some(left.len().cmp(&right.len()));
+        }
+    };
+    dump.finish()?;
+
+    let lengths = args.against_pattern.is_none();
+    if lengths && !args.quiet {
+        let file2 = args.file2.as_deref().unwrap_or_default();
+        note_eof(&args.file1, file2, eof_ordering);
+    }
+    Ok(differences > 0 || (lengths && eof_ordering.is_some_and(Ordering::is_ne)))
+}
+
+/// Write an HTML report of the comparison of both files, which are read into
+/// memory unless mapped.
+fn html(args: &Args, f1: Input, f2: Input) -> eyre::Result<bool> {
+    let (file1_size, file2_size) = (f1.size, f2.size);
+    let left = f1.load(args.skip1, args.length)?;
+    let left = (*left).as_ref();
+    // A pattern goes on for as long as the first file.
+    let length = match args.against_pattern {
+        Some(_) => Some(left.len() as u64),
+        None => args.length,
+    };
+    let right = f2.load(args.skip2, length)?;
+    let right = (*right).as_ref();
+
+    let compared = match args.pad {
+        Some(_) => left.len().max(right.len()),
+        None => left.len().min(right.len()),
+    } as u64;
+    let block_size = compared.div_ceil(HEATMAP_CELLS).max(1);
+
+    let differ = differ(args, left, right)?.heatmap(block_size);
+    let mut ranges = differ.parallel().ranges(args.gap);
+    let found = (&mut ranges).collect::<io::Result<Vec<_>>>()?;
+    let mut stats = ranges.stats();
+    let eof_ordering = ranges.eof_ordering();
+
+    let mut heatmap = stats.heatmap.take().expect("heatmap requested");
+    heatmap.cover(compared);
+
+    let lengths = args.pad.is_none() && args.against_pattern.is_none();
+    if lengths && !args.quiet && !args.tail {
+        let file2 = args.file2.as_deref().unwrap_or_default();
+        note_eof(&args.file1, file2, eof_ordering);
+    }
+    let different = stats.differences > 0 || (lengths && eof_ordering.is_some_and(Ordering::is_ne));
+
+    let summary = Summary {
+        masked: args.mask.is_some(),
+        tail: ranges.tail().filter(|_| args.tail),
+        ..Summary::new(file1_size, file2_size, stats, Some(found.len() as u64))
+    };
+    let report = Report {
+        names: [&args.file1, args.file2.as_deref().unwrap_or("pattern")],
+        data: [left, right],
+        skips: [args.skip1, args.skip2],
+        offsets: Offsets::new(args),
+    };
+    let found = &found[..found.len().min(args.max_diffs())];
+    report.write(writer(args), &summary, &heatmap, found)?;
+
+    Ok(different)
+}
+
+/// Browse both files, which are read into memory unless mapped.
+fn tui(args: &Args, f1: Input, f2: Input) -> eyre::Result<bool> {
+    let left = f1.load(args.skip1, args.length)?;
+    let right = f2.load(args.skip2, args.length)?;
+    let (left, right) = ((*left).as_ref(), (*right).as_ref());
+    let file2 = args.file2.as_deref().unwrap_or_default();
+
+    let browser = Browser::new(
+        [&args.file1, file2],
+        [left, right],
+        [args.skip1, args.skip2],
+        args.word_size / 8,
+        args.endian.into(),
+    )?
+    .run()?;
+
+    Ok(browser.differences() > 0 || left.len() != right.len())
+}
+
+/// Reconstruct the files into `image` by a majority vote, and report the bytes on which they disagree.
+fn vote(args: &Args, image: &str) -> eyre::Result<bool> {
+    let names: Vec<&String> = std::iter::once(&args.file1)
+        .chain(&args.file2)
+        .chain(&args.others)
+        .collect();
+
+    let mut sizes = Vec::new();
+    let mut sources = Vec::new();
+    for name in &names {
+        let f = Input::open(name)?;
+        sizes.push(f.size);
+        sources.push(f.stream(args.skip1, args.length)?);
+    }
+    // Any of the files may be replaced by the image.
+    let (staged, image) = Staged::create(image)?;
+    let mut vote = Vote::new(sources)
+        .start_offset(args.skip1)
+        .image(BufWriter::new(image));
+
+    let mut out = output::new(writer(args), args)?;
+    // The whole image is reconstructed, however many bytes are reported.
+    for (i, unstable) in (&mut vote).enumerate() {
+        let unstable = unstable?;
+        if i < args.max_diffs() && !args.report_only() {
+            out.unstable(&unstable)?;
+        }
+    }
+
+    let same_length = vote.same_length().unwrap_or(true);
+    if !same_length && !args.quiet {
+        eprintln!("NOTE: The files differ in length, only their common length was voted on.");
+    }
+
+    let stats = vote.stats();
+    let different = stats.unstable_bytes > 0 || !same_length;
+    let files = names
+        .iter()
+        .zip(sizes)
+        .zip(vote.deviations())
+        .map(|((name, size), stats)| FileSummary::new(name, size, stats))
+        .collect();
+    drop(vote);
+    staged.persist()?;
+    out.finish(&Summary::voted(stats, files))?;
+
+    Ok(different)
+}
+
+/// Compare ours and theirs against their base, and write their merge unless they conflict.
+fn merge(args: &Args) -> eyre::Result<bool> {
+    let (Some(ours), [theirs]) = (&args.file2, args.others.as_slice()) else {
+        eyre::bail!("A three-way comparison takes three files: base, ours and theirs");
+    };
+    let open = |name: &str| Input::open(name)?.stream(args.skip1, args.length);
+
+    // Any of the files may be replaced by the merge.
+    let (staged, merged): (_, Box<dyn Write>) = match &args.merged {
+        Some(path) => {
+            let (staged, f) = Staged::create(path)?;
+            (Some(staged), Box::new(BufWriter::new(f)))
+        }
+        None => (None, Box::new(io::sink())),
+    };
+    let mut merge = Merge::new(open(&args.file1)?, open(ours)?, open(theirs)?)
+        .start_offset(args.skip1)
+        .merged(merged);
+
+    let mut out = output::new(writer(args), args)?;
+    // The whole merge is written, however many ranges are reported.
+    for (i, range) in (&mut merge).enumerate() {
+        let range = range?;
+        if i < args.max_diffs() && !args.report_only() {
+            out.merge(&range)?;
+        }
+    }
+
+    let stats = merge.stats();
+    drop(merge);
+    let mergeable = stats.conflicts == 0;
+    match (staged, &args.merged) {
+        (Some(staged), _) if mergeable => staged.persist()?,
+        (_, Some(path)) if !args.quiet => {
+            eprintln!("NOTE: The merge was not written to {}.", path);
+        }
+        _ => {}
+    }
+    out.finish(&Summary::merged(stats))?;
+
+    Ok(!mergeable)
+}
+
+/// Standard output, or nowhere when quiet.
+fn writer(args: &Args) -> Box<dyn Write> {
+    match args.quiet {
+        true => Box::new(io::sink()),
+        false => Box::new(stdout().lock()),
+    }
+}
+
+fn differ<R1: Read, R2: Read>(args: &Args, f1: R1, f2: R2) -> eyre::Result<Differ<R1, R2>> {
+    let mask = match &args.mask {
+        Some(path) => Mask::parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| eyre::eyre!("Invalid mask {}: {}", path, e))?,
+        None => Mask::default(),
+    };
+
+    let word_size = args.value_type.map_or(args.word_size, ValueType::bits);
+    let histogram_width = args.histogram_width.unwrap_or(word_size);
+    if histogram_width < word_size {
+        eyre::bail!("The histogram width cannot be smaller than the word size");
+    }
+
+    let mut differ = Differ::new(f1, f2)
+        .start_offset(args.skip1)
+        .word_size(word_size / 8)
+        .endian(args.endian.into())
+        .histogram_word_size(histogram_width / 8)
+        .single_bitflip_only(args.single_bitflip_only)
+        .mask(mask);
+    if args.heatmap.is_some() {
+        differ = differ.heatmap(args.heatmap_block);
+    }
+
+    Ok(match args.pad {
+        Some(fill) => differ.pad(fill),
+        None => differ,
+    })
 }
 
-fn compare_buffers<T: Write>(
-    w: &mut T,
-    buffer1: &[u8],
-    buffer2: &[u8],
-    buffer_offset: usize,
-    format: &ValueOutputFormat,
-    bitflip_only: bool,
+fn report_differences(
+    args: &Args,
+    differences: impl Iterator<Item = io::Result<Difference>>,
+    out: &mut dyn Output,
 ) -> eyre::Result<()> {
-    for i in 0..buffer1.len() {
-        let v1 = buffer1[i];
-        let v2 = buffer2[i];
-        let offset = buffer_offset + i;
-
-        let is_diff = bitflip_only && is_bitflipped(v1, v2) || !bitflip_only && (v1 != v2);
-        if is_diff {
-            match format {
-                ValueOutputFormat::Binary => writeln!(w, "{:x}\t{:08b}\t{:08b}\t", offset, v1, v2)?,
-                ValueOutputFormat::Hex => writeln!(w, "{:x}\t{:x}\t{:x}\t", offset, v1, v2)?,
-                ValueOutputFormat::Decimal => writeln!(w, "{}\t{}\t{}\t", offset, v1, v2)?,
-                ValueOutputFormat::Combined => writeln!(
-                    w,
-                    "{}\t{:x}\t{}\t{:x}\t{}\t{:x}\t",
-                    offset, offset, v1, v1, v2, v2
-                )?,
-            }
+    // The whole heatmap is drawn, however many differences are reported.
+    let limit = match args.heatmap {
+        Some(_) => usize::MAX,
+        None => args.max_diffs(),
+    };
+    for (i, difference) in differences.take(limit).enumerate() {
+        let difference = difference?;
+        if i < args.max_diffs() && !args.report_only() {
+            out.difference(&difference)?;
         }
     }
     Ok(())
 }
 
-fn is_bitflipped(v1: u8, v2: u8) -> bool {
-    let v = v1 ^ v2;
-    if v == 0 {
-        return false;
+/// Report the ranges, returning their number.
+fn report_ranges(
+    args: &Args,
+    ranges: impl Iterator<Item = io::Result<DiffRange>>,
+    out: &mut dyn Output,
+) -> eyre::Result<u64> {
+    let mut count = 0;
+    for range in ranges.take(args.max_diffs()) {
+        let range = range?;
+        count += 1;
+        if !args.report_only() {
+            out.range(&range)?;
+        }
+    }
+    Ok(count)
+}
+
+fn note_eof(file1: &str, file2: &str, eof_ordering: Option<Ordering>) {
+    match eof_ordering {
+        Some(Ordering::Less) => eprintln!(
+            "NOTE: The second file ({}) is larger than the first file ({}).",
+            file2, file1
+        ),
+        Some(Ordering::Greater) => eprintln!(
+            "NOTE: The first file ({}) is larger than the second file ({}).",
+            file1, file2
+        ),
+        _ => (),
+    };
+}
+
+/// Align both files, which are read into memory, and report the spans which differ.
+fn align(
+    args: &Args,
+    mut f1: impl Read,
+    mut f2: impl Read,
+    out: &mut dyn Output,
+) -> eyre::Result<EditCounts> {
+    let (mut left, mut right) = (Vec::new(), Vec::new());
+    f1.read_to_end(&mut left)?;
+    f2.read_to_end(&mut right)?;
+
+    report_edits(args, bincmp::align(&left, &right).into_iter().map(Ok), out)
+}
+
+/// Compare both files as streams, and report the spans which differ.
+fn resync(
+    args: &Args,
+    f1: impl Read,
+    f2: impl Read,
+    out: &mut dyn Output,
+) -> eyre::Result<EditCounts> {
+    let resync = Resync::new(f1, f2)
+        .window(args.resync_window.try_into()?)
+        .threshold(args.resync_threshold);
+
+    report_edits(args, resync, out)
+}
+
+fn report_edits(
+    args: &Args,
+    edits: impl Iterator<Item = io::Result<Edit>>,
+    out: &mut dyn Output,
+) -> eyre::Result<EditCounts> {
+    let mut counts = EditCounts::default();
+    for edit in edits.take(args.max_diffs()) {
+        let mut edit = edit?;
+        edit.offset1 += args.skip1;
+        edit.offset2 += args.skip2;
+        counts.add(&edit);
+        if !args.report_only() {
+            out.edit(&edit)?;
+        }
     }
-    v & (v - 1) == 0
+
+    Ok(counts)
 }
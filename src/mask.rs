@@ -0,0 +1,246 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt, num::ParseIntError, ops::Range};
+
+/// Bits to exclude from the comparison, by offset.
+///
+/// A mask is parsed from lines of the form `START..END [BITS]`, or `OFFSET
+/// [BITS]` for a single byte, with `#` starting a comment. Offsets are
+/// decimal, or hexadecimal with a `0x` prefix, and `END` is exclusive.
+/// `BITS` is a hex string of the bits to ignore in each byte, repeated over
+/// the range: `0f` ignores the low nibble of every byte, `ff00` every other
+/// byte. Without `BITS`, the whole range is ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mask {
+    /// Non-overlapping ranges sorted by offset.
+    entries: Vec<MaskEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct MaskEntry {
+    range: Range<u64>,
+    bits: Vec<u8>,
+}
+
+/// A line of a mask that could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseMaskError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseMaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseMaskError {}
+
+impl Mask {
+    pub fn parse(s: &str) -> Result<Self, ParseMaskError> {
+        let mut entries = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let error = |message: String| ParseMaskError {
+                line: i + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(range) = fields.next() else {
+                continue;
+            };
+
+            let range = parse_range(range).map_err(error)?;
+            if range.is_empty() {
+                return Err(error(format!("empty range {:?}", range)));
+            }
+
+            let bits = match fields.next() {
+                Some(bits) => parse_hex(bits, "bits").map_err(error)?,
+                None => vec![0xff],
+            };
+            if let Some(field) = fields.next() {
+                return Err(error(format!("unexpected {:?}", field)));
+            }
+
+            entries.push((i + 1, MaskEntry { range, bits }));
+        }
+
+        entries.sort_by_key(|(_, entry)| entry.range.start);
+        for pair in entries.windows(2) {
+            let ((_, previous), (line, entry)) = (&pair[0], &pair[1]);
+            if entry.range.start < previous.range.end {
+                return Err(ParseMaskError {
+                    line: *line,
+                    message: format!("range {:?} overlaps {:?}", entry.range, previous.range),
+                });
+            }
+        }
+
+        Ok(Self {
+            entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bits of the byte at `offset` to ignore.
+    pub fn ignored(&self, offset: u64) -> u8 {
+        let i = self
+            .entries
+            .partition_point(|entry| entry.range.start <= offset);
+        match i.checked_sub(1).map(|i| &self.entries[i]) {
+            Some(entry) if entry.range.contains(&offset) => {
+                let index = (offset - entry.range.start) % entry.bits.len() as u64;
+                entry.bits[index as usize]
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// A decimal number, or a hexadecimal one with a `0x` prefix.
+pub fn parse_number(s: &str) -> Result<u64, ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn parse_range(s: &str) -> Result<Range<u64>, String> {
+    match s.split_once("..") {
+        Some((start, end)) => Ok(parse_offset(start)?..parse_offset(end)?),
+        None => {
+            let offset = parse_offset(s)?;
+            let end = offset
+                .checked_add(1)
+                .ok_or_else(|| format!("invalid offset {:?}: past the last byte", s))?;
+            Ok(offset..end)
+        }
+    }
+}
+
+fn parse_offset(s: &str) -> Result<u64, String> {
+    parse_number(s).map_err(|e| format!("invalid offset {:?}: {}", s, e))
+}
+
+/// Bytes given as pairs of hex digits, e.g. `55aa`, naming them `what` in errors.
+pub fn parse_hex(s: &str, what: &str) -> Result<Vec<u8>, String> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if s.is_empty() || !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(format!(
+            "invalid {} {:?}: expected pairs of hex digits",
+            what, s
+        ));
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid {} {:?}: {}", what, s, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(s: &str) -> ParseMaskError {
+        Mask::parse(s).unwrap_err()
+    }
+
+    #[test]
+    fn ranges() {
+        let mask = Mask::parse(
+            "# header\n\
+             \n\
+             0x10..0x12\n\
+             4  # single byte\n\
+             0X20..40 0f\n",
+        )
+        .unwrap();
+
+        assert_eq!(mask.ignored(3), 0);
+        assert_eq!(mask.ignored(4), 0xff);
+        assert_eq!(mask.ignored(5), 0);
+        assert_eq!(mask.ignored(0xf), 0);
+        assert_eq!(mask.ignored(0x10), 0xff);
+        assert_eq!(mask.ignored(0x11), 0xff);
+        assert_eq!(mask.ignored(0x12), 0);
+        assert_eq!(mask.ignored(0x20), 0x0f);
+        assert_eq!(mask.ignored(39), 0x0f);
+        assert_eq!(mask.ignored(40), 0);
+    }
+
+    #[test]
+    fn bit_patterns() {
+        let mask = Mask::parse("10..15 0xff00f0").unwrap();
+
+        let ignored: Vec<_> = (9..16).map(|offset| mask.ignored(offset)).collect();
+        assert_eq!(ignored, [0, 0xff, 0x00, 0xf0, 0xff, 0x00, 0]);
+    }
+
+    #[test]
+    fn overlapping() {
+        assert!(Mask::parse("0..4\n4..8\n8").is_ok());
+        assert_eq!(
+            error("8..16\n0..4\n2..5"),
+            ParseMaskError {
+                line: 3,
+                message: "range 2..5 overlaps 0..4".to_string(),
+            }
+        );
+        assert_eq!(error("0..10\n9").line, 2);
+    }
+
+    #[test]
+    fn last_byte() {
+        let mask = Mask::parse("0xfffffffffffffffe..0xffffffffffffffff").unwrap();
+        assert_eq!(mask.ignored(u64::MAX - 1), 0xff);
+
+        let e = error("0\n0xffffffffffffffff");
+        assert_eq!(e.line, 2);
+        assert!(e.message.contains("past the last byte"), "{}", e);
+    }
+
+    #[test]
+    fn malformed() {
+        for (s, message) in [
+            ("0..", "invalid offset \"\""),
+            ("x10", "invalid offset \"x10\""),
+            ("0x10..0x8", "empty range 16..8"),
+            ("0..4 f", "invalid bits \"f\""),
+            ("0..4 zz", "invalid bits \"zz\""),
+            ("0..4 0f 0f", "unexpected \"0f\""),
+        ] {
+            let e = error(&format!("# comment\n{}", s));
+            assert_eq!(e.line, 2, "{}", s);
+            assert!(e.message.starts_with(message), "{}: {}", s, e);
+        }
+    }
+}
@@ -0,0 +1,431 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Sink, Write},
+};
+
+use serde::Serialize;
+
+use crate::{differ::read_full, BUFFER_SIZE, PREVIEW_SIZE};
+
+/// Which side changed a range of the base.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeKind {
+    /// Changed in ours only.
+    Ours,
+    /// Changed in theirs only.
+    Theirs,
+    /// Changed the same way in both.
+    Identical,
+    /// Changed differently in both.
+    Conflict,
+}
+
+impl MergeKind {
+    /// How `ours` and `theirs` changed `base`, if at all, `None` being past
+    /// the end of a source.
+    fn of(base: Option<u8>, ours: Option<u8>, theirs: Option<u8>) -> Option<Self> {
+        // Bytes cut from the base cannot be told apart from a shorter file.
+        if base.is_some() && (ours.is_none() || theirs.is_none()) {
+            return Some(MergeKind::Conflict);
+        }
+        match (ours != base, theirs != base) {
+            (false, false) => None,
+            (true, false) => Some(MergeKind::Ours),
+            (false, true) => Some(MergeKind::Theirs),
+            (true, true) if ours == theirs => Some(MergeKind::Identical),
+            (true, true) => Some(MergeKind::Conflict),
+        }
+    }
+}
+
+impl fmt::Display for MergeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MergeKind::Ours => "ours",
+            MergeKind::Theirs => "theirs",
+            MergeKind::Identical => "identical",
+            MergeKind::Conflict => "conflict",
+        })
+    }
+}
+
+/// A run of bytes of the base changed the same way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeRange {
+    pub kind: MergeKind,
+    /// Offset of the first changed byte.
+    pub start: u64,
+    /// Offset following the last changed byte.
+    pub end: u64,
+    /// Up to [`PREVIEW_SIZE`] bytes of each source from `start`, fewer past
+    /// its end.
+    pub base: Vec<u8>,
+    pub ours: Vec<u8>,
+    pub theirs: Vec<u8>,
+}
+
+impl MergeRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn push(&mut self, base: Option<u8>, ours: Option<u8>, theirs: Option<u8>) {
+        if self.len() < PREVIEW_SIZE as u64 {
+            self.base.extend(base);
+            self.ours.extend(ours);
+            self.theirs.extend(theirs);
+        }
+        self.end += 1;
+    }
+}
+
+/// Aggregate figures of a three-way comparison.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Number of bytes compared.
+    pub compared: u64,
+    /// Number of ranges of each kind.
+    pub ours: u64,
+    pub theirs: u64,
+    pub identical: u64,
+    pub conflicts: u64,
+    /// Number of bytes within conflicting ranges.
+    pub conflicting_bytes: u64,
+}
+
+impl MergeStats {
+    pub fn add(&mut self, range: &MergeRange) {
+        match range.kind {
+            MergeKind::Ours => self.ours += 1,
+            MergeKind::Theirs => self.theirs += 1,
+            MergeKind::Identical => self.identical += 1,
+            MergeKind::Conflict => {
+                self.conflicts += 1;
+                self.conflicting_bytes += range.len();
+            }
+        }
+    }
+}
+
+/// Three-way comparison of two sources changed from a common base.
+///
+/// Every byte changed in either source is classified by [`MergeKind`], and
+/// runs of bytes of the same kind are yielded as [`MergeRange`]s. The sources
+/// are compared offset by offset, in chunks of [`BUFFER_SIZE`] bytes, until
+/// the longest one ends. Bytes appended to the base by one source are changed
+/// in that source only, while a source shorter than the base conflicts. The
+/// merge of both, taking the base where they conflict, is written to the
+/// [`Merge::merged`] writer as it goes.
+pub struct Merge<R, W = Sink> {
+    /// Base, ours and theirs.
+    sources: [R; 3],
+    merged: W,
+    buffers: [[u8; BUFFER_SIZE]; 3],
+    /// Offset of the current chunk.
+    offset: u64,
+    /// Whether all sources ended together, once all of them did.
+    eof: Option<bool>,
+    /// Whether a source ended before the others.
+    uneven: bool,
+    /// Last range found, which may continue in the next chunk.
+    current: Option<MergeRange>,
+    pending: VecDeque<MergeRange>,
+    stats: MergeStats,
+}
+
+impl<R: Read> Merge<R> {
+    pub fn new(base: R, ours: R, theirs: R) -> Self {
+        Self {
+            sources: [base, ours, theirs],
+            merged: io::sink(),
+            buffers: [[0u8; BUFFER_SIZE]; 3],
+            offset: 0,
+            eof: None,
+            uneven: false,
+            current: None,
+            pending: VecDeque::new(),
+            stats: MergeStats::default(),
+        }
+    }
+}
+
+impl<R: Read, W: Write> Merge<R, W> {
+    /// Offset reported for the first byte, for sources which do not start at
+    /// the beginning of their file.
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Write the merge of both sources to `merged`.
+    pub fn merged<W2: Write>(self, merged: W2) -> Merge<R, W2> {
+        Merge {
+            sources: self.sources,
+            merged,
+            buffers: self.buffers,
+            offset: self.offset,
+            eof: self.eof,
+            uneven: self.uneven,
+            current: self.current,
+            pending: self.pending,
+            stats: self.stats,
+        }
+    }
+
+    /// Statistics of the comparison, complete once it is done.
+    pub fn stats(&self) -> MergeStats {
+        self.stats.clone()
+    }
+
+    /// Whether all sources have the same length, once the comparison is done.
+    pub fn same_length(&self) -> Option<bool> {
+        self.eof
+    }
+
+    /// Read and compare the next chunk.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut lengths = [0; 3];
+        for ((source, buffer), n) in self
+            .sources
+            .iter_mut()
+            .zip(&mut self.buffers)
+            .zip(&mut lengths)
+        {
+            *n = read_full(source, buffer)?;
+        }
+        let len = lengths.iter().copied().max().unwrap_or_default();
+        self.uneven |= lengths.iter().any(|&n| n != len);
+        if len < BUFFER_SIZE {
+            self.eof = Some(!self.uneven);
+        }
+
+        let [base, ours, theirs] = self.buffers;
+        let mut merged = base;
+        let mut merged_len = 0;
+        if lengths.iter().all(|&n| n == len)
+            && ours[..len] == base[..len]
+            && theirs[..len] == base[..len]
+        {
+            self.close();
+            merged_len = len;
+        } else {
+            let byte =
+                |buffer: &[u8; BUFFER_SIZE], n: usize, pos: usize| (pos < n).then(|| buffer[pos]);
+            for pos in 0..len {
+                let offset = self.offset + pos as u64;
+                let b = byte(&base, lengths[0], pos);
+                let o = byte(&ours, lengths[1], pos);
+                let t = byte(&theirs, lengths[2], pos);
+                let kind = MergeKind::of(b, o, t);
+                let m = match kind {
+                    None | Some(MergeKind::Conflict) => b,
+                    Some(MergeKind::Ours | MergeKind::Identical) => o,
+                    Some(MergeKind::Theirs) => t,
+                };
+                if let Some(m) = m {
+                    merged[merged_len] = m;
+                    merged_len += 1;
+                }
+                let Some(kind) = kind else {
+                    self.close();
+                    continue;
+                };
+
+                match &mut self.current {
+                    Some(current) if current.kind == kind => current.push(b, o, t),
+                    _ => {
+                        self.close();
+                        let mut range = MergeRange {
+                            kind,
+                            start: offset,
+                            end: offset,
+                            base: Vec::with_capacity(PREVIEW_SIZE),
+                            ours: Vec::with_capacity(PREVIEW_SIZE),
+                            theirs: Vec::with_capacity(PREVIEW_SIZE),
+                        };
+                        range.push(b, o, t);
+                        self.current = Some(range);
+                    }
+                }
+            }
+        }
+        self.merged.write_all(&merged[..merged_len])?;
+
+        self.stats.compared += len as u64;
+        self.offset += len as u64;
+
+        if self.eof.is_some() {
+            self.close();
+            self.merged.flush()?;
+        }
+        Ok(())
+    }
+
+    /// End the current range, if any.
+    fn close(&mut self) {
+        if let Some(range) = self.current.take() {
+            self.stats.add(&range);
+            self.pending.push_back(range);
+        }
+    }
+
+    fn next_range(&mut self) -> io::Result<Option<MergeRange>> {
+        while self.pending.is_empty() && self.eof.is_none() {
+            self.fill()?;
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+impl<R: Read, W: Write> Iterator for Merge<R, W> {
+    type Item = io::Result<MergeRange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_range().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ranges and merge of three sources.
+    fn merge(base: &[u8], ours: &[u8], theirs: &[u8]) -> (Vec<MergeRange>, Vec<u8>) {
+        let mut merged = Vec::new();
+        let ranges = Merge::new(base, ours, theirs)
+            .merged(&mut merged)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        (ranges, merged)
+    }
+
+    fn kinds(ranges: &[MergeRange]) -> Vec<(MergeKind, u64, u64)> {
+        ranges
+            .iter()
+            .map(|range| (range.kind, range.start, range.end))
+            .collect()
+    }
+
+    #[test]
+    fn changes() {
+        let (ranges, merged) = merge(b"0123456789", b"0ab3456789", b"01234x67y9");
+
+        assert_eq!(
+            kinds(&ranges),
+            [
+                (MergeKind::Ours, 1, 3),
+                (MergeKind::Theirs, 5, 6),
+                (MergeKind::Theirs, 8, 9)
+            ]
+        );
+        assert_eq!(merged, b"0ab34x67y9");
+    }
+
+    #[test]
+    fn conflicts() {
+        let (ranges, _) = merge(b"0123456789", b"0ab3456789", b"0a23x56789");
+
+        assert_eq!(
+            kinds(&ranges),
+            [
+                (MergeKind::Identical, 1, 2),
+                (MergeKind::Ours, 2, 3),
+                (MergeKind::Theirs, 4, 5)
+            ]
+        );
+
+        let (ranges, _) = merge(b"0123456789", b"0ab3456789", b"01c3456789");
+        assert_eq!(
+            kinds(&ranges),
+            [(MergeKind::Ours, 1, 2), (MergeKind::Conflict, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn appends() {
+        let (ranges, merged) = merge(b"0123", b"0123abc", b"x123");
+        assert_eq!(
+            kinds(&ranges),
+            [(MergeKind::Theirs, 0, 1), (MergeKind::Ours, 4, 7)]
+        );
+        assert_eq!(
+            (
+                &ranges[1].base[..],
+                &ranges[1].ours[..],
+                &ranges[1].theirs[..]
+            ),
+            (&b""[..], &b"abc"[..], &b""[..])
+        );
+        assert_eq!(merged, b"x123abc");
+
+        let (ranges, merged) = merge(b"0123", b"0123ab", b"0123ab");
+        assert_eq!(kinds(&ranges), [(MergeKind::Identical, 4, 6)]);
+        assert_eq!(merged, b"0123ab");
+
+        let (ranges, _) = merge(b"0123", b"0123ab", b"0123a");
+        assert_eq!(
+            kinds(&ranges),
+            [(MergeKind::Identical, 4, 5), (MergeKind::Ours, 5, 6)]
+        );
+
+        let (ranges, _) = merge(b"0123", b"0123ab", b"0123xy");
+        assert_eq!(kinds(&ranges), [(MergeKind::Conflict, 4, 6)]);
+    }
+
+    #[test]
+    fn shorter_than_base() {
+        let (ranges, _) = merge(b"0123456789", b"01234567", b"0123456789");
+        assert_eq!(kinds(&ranges), [(MergeKind::Conflict, 8, 10)]);
+
+        let mut merge = Merge::new(&b"0123"[..], &b"0123"[..], &b"0123ab"[..]);
+        (&mut merge).for_each(drop);
+        assert_eq!(merge.same_length(), Some(false));
+        assert_eq!(merge.stats().compared, 6);
+    }
+
+    #[test]
+    fn appends_across_chunks() {
+        let base: Vec<u8> = (0..BUFFER_SIZE + 10).map(|i| i as u8).collect();
+        let mut ours = base.clone();
+        ours.extend((0..BUFFER_SIZE).map(|i| !i as u8));
+        let mut theirs = base.clone();
+        theirs[3] ^= 0xff;
+
+        let (ranges, merged) = merge(&base, &ours, &theirs);
+        let end = 2 * BUFFER_SIZE as u64 + 10;
+        assert_eq!(
+            kinds(&ranges),
+            [
+                (MergeKind::Theirs, 3, 4),
+                (MergeKind::Ours, base.len() as u64, end)
+            ]
+        );
+        let mut expected = ours.clone();
+        expected[3] ^= 0xff;
+        assert_eq!(merged, expected);
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    cmp::Ordering,
+    io::{self, Read},
+};
+
+use crate::{Differ, Difference, Stats};
+
+/// Differences of several sources from a reference, at the same offset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiDifference {
+    pub offset: u64,
+    /// Size of the word in bytes.
+    pub size: usize,
+    /// Value in the reference.
+    pub reference: u64,
+    /// Value in each source, unless equal to the reference.
+    pub values: Vec<Option<u64>>,
+}
+
+/// Comparison of several sources against a common reference.
+///
+/// Each source is compared by its own [`Differ`], reading the reference
+/// separately, and the differences of all of them are merged by offset.
+pub struct MultiDiffer<R0, R> {
+    differs: Vec<Differ<R0, R>>,
+    /// Next difference of each comparison.
+    heads: Vec<Option<Difference>>,
+}
+
+impl<R0: Read, R: Read> MultiDiffer<R0, R> {
+    /// Comparisons of the reference, as the first source, with each of the others.
+    pub fn new(differs: Vec<Differ<R0, R>>) -> Self {
+        let heads = vec![None; differs.len()];
+        Self { differs, heads }
+    }
+
+    /// Statistics of each comparison, see [`Differ::stats`].
+    pub fn stats(&self) -> Vec<Stats> {
+        self.differs.iter().map(Differ::stats).collect()
+    }
+
+    /// How the length of the reference compares to each source, see [`Differ::eof_ordering`].
+    pub fn eof_orderings(&self) -> Vec<Option<Ordering>> {
+        self.differs.iter().map(Differ::eof_ordering).collect()
+    }
+
+    fn next_difference(&mut self) -> io::Result<Option<MultiDifference>> {
+        for (differ, head) in self.differs.iter_mut().zip(&mut self.heads) {
+            if head.is_none() {
+                *head = differ.next().transpose()?;
+            }
+        }
+
+        let Some(first) = self
+            .heads
+            .iter()
+            .flatten()
+            .min_by_key(|d| d.offset)
+            .copied()
+        else {
+            return Ok(None);
+        };
+
+        let values = self
+            .heads
+            .iter_mut()
+            .map(|head| match head {
+                Some(d) if d.offset == first.offset => head.take().map(|d| d.right),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Some(MultiDifference {
+            offset: first.offset,
+            size: first.size,
+            reference: first.left,
+            values,
+        }))
+    }
+}
+
+impl<R0: Read, R: Read> Iterator for MultiDiffer<R0, R> {
+    type Item = io::Result<MultiDifference>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_difference().transpose()
+    }
+}
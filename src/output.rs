@@ -0,0 +1,1370 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Write;
+
+use bincmp::{
+    DiffRange, Difference, Edit, EditKind, MergeKind, MergeRange, MergeStats, MultiDifference,
+    Stats, Tail, UnstableByte, VoteStats, PREVIEW_SIZE,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use tabwriter::TabWriter;
+
+use crate::{
+    value::{Value, ValueType},
+    Args,
+};
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum ValueOutputFormat {
+    Hex,
+    Decimal,
+    Binary,
+    Combined,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum OutputFormat {
+    /// Aligned table, values formatted according to --format
+    Table,
+    /// A single JSON document
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma separated values
+    Csv,
+    /// Tab separated values
+    Tsv,
+    /// A static HTML report, with a heatmap and hexdumps of the ranges
+    Html,
+}
+
+/// Totals reported once the comparison is done.
+#[derive(Serialize, Debug)]
+pub struct Summary {
+    /// Sizes of the files, unknown for pipes.
+    pub file1_size: Option<u64>,
+    pub file2_size: Option<u64>,
+    /// Statistics of the differences, unless aligning.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bit_error_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranges: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edits: Option<EditCounts>,
+    /// Bytes past the end of the shorter file, if reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tail: Option<Tail>,
+    /// Statistics of each file, when comparing several against the first one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileSummary>>,
+    /// Statistics of a majority vote.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vote: Option<VoteStats>,
+    /// Statistics of a three-way comparison.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merge: Option<MergeStats>,
+    /// Whether a mask was applied.
+    #[serde(skip)]
+    pub masked: bool,
+}
+
+/// Statistics of one of several files compared against the first one.
+#[derive(Serialize, Debug)]
+pub struct FileSummary {
+    pub file: String,
+    pub size: Option<u64>,
+    #[serde(flatten)]
+    pub stats: Stats,
+    pub bit_error_rate: f64,
+}
+
+impl FileSummary {
+    pub fn new(file: &str, size: Option<u64>, stats: Stats) -> Self {
+        Self {
+            file: file.to_string(),
+            size,
+            bit_error_rate: stats.bit_error_rate(),
+            stats,
+        }
+    }
+}
+
+/// Totals of an alignment.
+#[derive(Serialize, Debug, Default)]
+pub struct EditCounts {
+    pub edits: u64,
+    pub inserted: u64,
+    pub deleted: u64,
+    /// Bytes of the first file replaced.
+    pub replaced: u64,
+}
+
+impl EditCounts {
+    fn report(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("edits", self.edits.to_string()),
+            ("inserted bytes", self.inserted.to_string()),
+            ("deleted bytes", self.deleted.to_string()),
+            ("replaced bytes", self.replaced.to_string()),
+        ]
+    }
+
+    pub fn add(&mut self, edit: &Edit) {
+        self.edits += 1;
+        match edit.kind {
+            EditKind::Insert => self.inserted += edit.len2,
+            EditKind::Delete => self.deleted += edit.len1,
+            EditKind::Replace => self.replaced += edit.len1,
+        }
+    }
+}
+
+impl Summary {
+    pub fn new(
+        file1_size: Option<u64>,
+        file2_size: Option<u64>,
+        stats: Stats,
+        ranges: Option<u64>,
+    ) -> Self {
+        Self {
+            file1_size,
+            file2_size,
+            bit_error_rate: Some(stats.bit_error_rate()),
+            stats: Some(stats),
+            ranges,
+            edits: None,
+            tail: None,
+            files: None,
+            vote: None,
+            merge: None,
+            masked: false,
+        }
+    }
+
+    pub fn aligned(file1_size: Option<u64>, file2_size: Option<u64>, edits: EditCounts) -> Self {
+        Self {
+            file1_size,
+            file2_size,
+            stats: None,
+            bit_error_rate: None,
+            ranges: None,
+            edits: Some(edits),
+            tail: None,
+            files: None,
+            vote: None,
+            merge: None,
+            masked: false,
+        }
+    }
+
+    /// Statistics of several files compared against the first one.
+    pub fn multi(file1_size: Option<u64>, files: Vec<FileSummary>) -> Self {
+        Self {
+            file1_size,
+            file2_size: None,
+            stats: None,
+            bit_error_rate: None,
+            ranges: None,
+            edits: None,
+            tail: None,
+            files: Some(files),
+            vote: None,
+            merge: None,
+            masked: false,
+        }
+    }
+
+    /// Statistics of a majority vote, and of each file against its outcome.
+    pub fn voted(vote: VoteStats, files: Vec<FileSummary>) -> Self {
+        Self {
+            file1_size: None,
+            file2_size: None,
+            stats: None,
+            bit_error_rate: None,
+            ranges: None,
+            edits: None,
+            tail: None,
+            files: Some(files),
+            vote: Some(vote),
+            merge: None,
+            masked: false,
+        }
+    }
+
+    /// Statistics of a three-way comparison.
+    pub fn merged(merge: MergeStats) -> Self {
+        Self {
+            file1_size: None,
+            file2_size: None,
+            stats: None,
+            bit_error_rate: None,
+            ranges: None,
+            edits: None,
+            tail: None,
+            files: None,
+            vote: None,
+            merge: Some(merge),
+            masked: false,
+        }
+    }
+
+    /// Label and value of each statistic, in report order.
+    pub fn report(
+        &self,
+        format: &ValueOutputFormat,
+        offsets: &Offsets,
+    ) -> Vec<(&'static str, String)> {
+        if let Some(vote) = &self.vote {
+            return report_vote(vote, format);
+        }
+        if let Some(merge) = &self.merge {
+            return report_merge(merge);
+        }
+        let Some(stats) = &self.stats else {
+            return self.edits.iter().flat_map(EditCounts::report).collect();
+        };
+
+        let mut report = report_stats(stats, self.masked, format, offsets);
+        if let Some(ranges) = self.ranges {
+            report.push(("ranges", ranges.to_string()));
+        }
+        if let Some(tail) = &self.tail {
+            report.push(("tail", format_tail(tail, format, offsets)));
+        }
+        report
+    }
+}
+
+/// Label and value of each statistic of a comparison, in report order.
+fn report_stats(
+    stats: &Stats,
+    masked: bool,
+    format: &ValueOutputFormat,
+    offsets: &Offsets,
+) -> Vec<(&'static str, String)> {
+    let offset = |offset: Option<u64>| match offset {
+        None => "-".to_string(),
+        Some(offset) => format_offset(offset, format, offsets),
+    };
+
+    let mut report = vec![
+        ("compared bytes", stats.compared.to_string()),
+        ("differing words", stats.differences.to_string()),
+        ("flipped bits", stats.flipped_bits.to_string()),
+        ("bit error rate", format_rate(stats.bit_error_rate())),
+        ("single-bit errors", stats.single_bit_errors.to_string()),
+        ("multi-bit errors", stats.multi_bit_errors.to_string()),
+        ("0->1 flips", stats.set_bits.to_string()),
+        ("1->0 flips", stats.cleared_bits.to_string()),
+        ("first difference", offset(stats.first_offset)),
+        ("last difference", offset(stats.last_offset)),
+    ];
+    if masked {
+        report.push(("masked differences", stats.masked_differences.to_string()));
+        report.push(("masked bits", stats.masked_bits.to_string()));
+    }
+    report
+}
+
+/// Label and value of each statistic of a vote, in report order.
+fn report_vote(vote: &VoteStats, format: &ValueOutputFormat) -> Vec<(&'static str, String)> {
+    let offset = |offset: Option<u64>| match (offset, format) {
+        (None, _) => "-".to_string(),
+        (Some(offset), ValueOutputFormat::Decimal) => offset.to_string(),
+        (Some(offset), _) => format!("0x{:x}", offset),
+    };
+
+    vec![
+        ("voted bytes", vote.voted.to_string()),
+        ("unstable bytes", vote.unstable_bytes.to_string()),
+        ("unstable bits", vote.unstable_bits.to_string()),
+        ("tied bits", vote.tied_bits.to_string()),
+        ("confidence", format_confidence(vote.confidence)),
+        ("first unstable", offset(vote.first_offset)),
+        ("last unstable", offset(vote.last_offset)),
+    ]
+}
+
+/// Label and value of each statistic of a three-way comparison, in report order.
+fn report_merge(merge: &MergeStats) -> Vec<(&'static str, String)> {
+    vec![
+        ("compared bytes", merge.compared.to_string()),
+        ("changed in ours", merge.ours.to_string()),
+        ("changed in theirs", merge.theirs.to_string()),
+        ("identical changes", merge.identical.to_string()),
+        ("conflicts", merge.conflicts.to_string()),
+        ("conflicting bytes", merge.conflicting_bytes.to_string()),
+    ]
+}
+
+/// A share of the votes, as a percentage.
+fn format_confidence(confidence: f64) -> String {
+    format!("{:.1}%", confidence * 100.0)
+}
+
+/// An offset of the first file, along with that of the second one if they differ.
+fn format_offset(offset: u64, format: &ValueOutputFormat, offsets: &Offsets) -> String {
+    let number = |offset: u64| match format {
+        ValueOutputFormat::Decimal => offset.to_string(),
+        _ => format!("0x{:x}", offset),
+    };
+    match offsets.file2(offset) {
+        None => number(offset),
+        Some(offset2) => format!("{} / {}", number(offset), number(offset2)),
+    }
+}
+
+/// A bit error rate, e.g. `1.234e-5`.
+fn format_rate(rate: f64) -> String {
+    format!("{:.3e}", rate)
+}
+
+/// E.g. `16 bytes of file 2 from 0x400, all 0xff`.
+fn format_tail(tail: &Tail, format: &ValueOutputFormat, offsets: &Offsets) -> String {
+    let mut s = format!(
+        "{} bytes of file {} from {}",
+        tail.len(),
+        tail.file,
+        format_offset(tail.start, format, offsets)
+    );
+    if let Some(padding) = tail.padding {
+        s += &format!(", all 0x{:02x}", padding);
+    }
+    s
+}
+
+/// A sink for the comparison results.
+pub trait Output {
+    fn difference(&mut self, difference: &Difference) -> eyre::Result<()>;
+    fn range(&mut self, range: &DiffRange) -> eyre::Result<()>;
+    fn edit(&mut self, edit: &Edit) -> eyre::Result<()>;
+    fn multi(&mut self, difference: &MultiDifference) -> eyre::Result<()>;
+    fn unstable(&mut self, unstable: &UnstableByte) -> eyre::Result<()>;
+    fn merge(&mut self, range: &MergeRange) -> eyre::Result<()>;
+    /// The bytes of the longer file past the end of the shorter one, after
+    /// the differences or ranges.
+    fn tail(&mut self, tail: &Tail) -> eyre::Result<()>;
+    fn finish(&mut self, summary: &Summary) -> eyre::Result<()>;
+}
+
+pub fn new<'a, W: Write + 'a>(w: W, args: &Args) -> eyre::Result<Box<dyn Output + 'a>> {
+    Ok(match args.output {
+        OutputFormat::Table => Box::new(Table::new(w, args)?),
+        OutputFormat::Json => Box::new(Json::new(w, args)),
+        OutputFormat::Ndjson => Box::new(Ndjson::new(w, args)),
+        OutputFormat::Csv => Box::new(Csv::new(w, b',', args)?),
+        OutputFormat::Tsv => Box::new(Csv::new(w, b'\t', args)?),
+        OutputFormat::Html => eyre::bail!("HTML reports compare two files offset by offset"),
+    })
+}
+
+/// Maps offsets within the first file, as reported by the comparison, to
+/// offsets within the second one.
+#[derive(Clone, Copy, Debug)]
+pub struct Offsets {
+    skip1: u64,
+    skip2: u64,
+}
+
+impl Offsets {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            skip1: args.skip1,
+            // A pattern has no offsets of its own, and votes and three-way
+            // comparisons read every file from the same offset.
+            skip2: match (&args.against_pattern, &args.vote, args.three_way) {
+                (None, None, false) => args.skip2,
+                _ => args.skip1,
+            },
+        }
+    }
+
+    /// Whether the files are compared from different offsets, which then
+    /// have to be reported for each file.
+    pub fn split(&self) -> bool {
+        self.skip1 != self.skip2
+    }
+
+    /// The offset in the second file, if different from the first one.
+    pub fn file2(&self, offset: u64) -> Option<u64> {
+        self.split().then(|| offset - self.skip1 + self.skip2)
+    }
+}
+
+/// Typed values of both sides of a difference and their delta.
+fn typed(difference: &Difference, value_type: ValueType) -> (Value, Value, Value) {
+    let left = value_type.decode(difference.left);
+    let right = value_type.decode(difference.right);
+    (left, right, Value::delta(left, right))
+}
+
+/// Hex string of a range preview, with an ellipsis if the range is longer.
+///
+/// A preview cut short by the end of its file has no ellipsis.
+fn preview(len: u64, bytes: &[u8]) -> String {
+    let mut s: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    if bytes.len() == PREVIEW_SIZE && len > PREVIEW_SIZE as u64 {
+        s.push_str("...");
+    }
+    s
+}
+
+/// Previews of a tail as those of a range, empty for the shorter file.
+fn tail_previews(tail: &Tail) -> (String, String) {
+    let preview = preview(tail.len(), &tail.preview);
+    match tail.file {
+        1 => (preview, String::new()),
+        _ => (String::new(), preview),
+    }
+}
+
+pub struct Table<W: Write> {
+    tw: TabWriter<W>,
+    format: ValueOutputFormat,
+    offsets: Offsets,
+    value_type: Option<ValueType>,
+    report_only: bool,
+    ranges: bool,
+    stats: bool,
+    histogram: bool,
+}
+
+impl<W: Write> Table<W> {
+    pub fn new(w: W, args: &Args) -> eyre::Result<Self> {
+        let mut table = Self {
+            tw: TabWriter::new(w)
+                .padding(5)
+                .alignment(tabwriter::Alignment::Right),
+            format: args.format.clone(),
+            offsets: Offsets::new(args),
+            value_type: args.value_type,
+            report_only: args.report_only(),
+            ranges: args.ranges,
+            stats: args.stats,
+            histogram: args.histogram,
+        };
+
+        let offset = match (&table.format, table.offsets.split()) {
+            (ValueOutputFormat::Combined, false) => "OFFSET\tHex",
+            (ValueOutputFormat::Combined, true) => "OFFSET1\tHex\tOFFSET2\tHex",
+            (_, false) => "OFFSET",
+            (_, true) => "OFFSET1\tOFFSET2",
+        };
+        let range = match table.offsets.split() {
+            false => "RANGE",
+            true => "RANGE1\tRANGE2",
+        };
+        let tw = &mut table.tw;
+
+        match &args.format {
+            _ if args.report_only() => (),
+            _ if args.edits() => writeln!(tw, "EDIT\tOFFSET1\tLENGTH1\tOFFSET2\tLENGTH2\t")?,
+            _ if args.three_way => writeln!(tw, "KIND\t{}\tLENGTH\tBASE\tOURS\tTHEIRS\t", range)?,
+            _ if args.ranges => writeln!(tw, "{}\tLENGTH\tFILE1\tFILE2\t", range)?,
+            format if args.vote.is_some() => {
+                write!(tw, "{}\t", offset)?;
+                let files = (1..=args.others.len() + 2).map(|i| format!("FILE{}", i));
+                let columns = std::iter::once("VOTE".to_string())
+                    .chain(files)
+                    .chain(std::iter::once("BITS".to_string()));
+                for column in columns {
+                    match format {
+                        ValueOutputFormat::Combined => write!(tw, "{}\tHex\t", column)?,
+                        _ => write!(tw, "{}\t", column)?,
+                    }
+                }
+                writeln!(tw, "CONFIDENCE\t")?;
+            }
+            format if args.multi() => {
+                write!(tw, "{}\t", offset)?;
+                for i in 1..=args.others.len() + 2 {
+                    match format {
+                        ValueOutputFormat::Combined => write!(tw, "FILE{}\tHex\t", i)?,
+                        _ => write!(tw, "FILE{}\t", i)?,
+                    }
+                }
+                writeln!(tw)?;
+            }
+            _ if args.value_type.is_some() => writeln!(tw, "{}\tFILE1\tFILE2\tDELTA\t", offset)?,
+            ValueOutputFormat::Combined => writeln!(tw, "{}\tFILE1\tHex\tFILE2\tHex\t", offset)?,
+            _ => writeln!(tw, "{}\tFILE1\tFILE2\t", offset)?,
+        }
+
+        Ok(table)
+    }
+
+    /// Offset columns of a row.
+    fn offset(&self, offset: u64) -> String {
+        let cells = |offset: u64| match self.format {
+            ValueOutputFormat::Decimal => offset.to_string(),
+            ValueOutputFormat::Combined => format!("{}\t{:x}", offset, offset),
+            _ => format!("{:x}", offset),
+        };
+
+        match self.offsets.file2(offset) {
+            None => cells(offset),
+            Some(offset2) => format!("{}\t{}", cells(offset), cells(offset2)),
+        }
+    }
+
+    /// Value columns of a row, for words of `bits` bits.
+    fn value(&self, value: u64, bits: usize) -> String {
+        match self.format {
+            ValueOutputFormat::Binary => format!("{:0bits$b}", value),
+            ValueOutputFormat::Hex => format!("{:x}", value),
+            ValueOutputFormat::Decimal => value.to_string(),
+            ValueOutputFormat::Combined => format!("{}\t{:x}", value, value),
+        }
+    }
+
+    /// Range columns of a row.
+    fn range_span(&self, start: u64, end: u64) -> String {
+        let span = |start: u64, end: u64| match self.format {
+            ValueOutputFormat::Decimal => format!("{}..{}", start, end),
+            _ => format!("{:x}..{:x}", start, end),
+        };
+
+        match self.offsets.file2(start) {
+            None => span(start, end),
+            Some(start2) => format!(
+                "{}\t{}",
+                span(start, end),
+                span(start2, start2 + (end - start))
+            ),
+        }
+    }
+}
+
+impl<W: Write> Output for Table<W> {
+    fn difference(&mut self, difference: &Difference) -> eyre::Result<()> {
+        let Difference {
+            left: v1,
+            right: v2,
+            ..
+        } = *difference;
+        let offset = self.offset(difference.offset);
+        let bits = difference.bits() as usize;
+
+        if let Some(value_type) = self.value_type {
+            let (v1, v2, delta) = typed(difference, value_type);
+            writeln!(self.tw, "{}\t{}\t{}\t{}\t", offset, v1, v2, delta)?;
+            return Ok(());
+        }
+
+        let (v1, v2) = (self.value(v1, bits), self.value(v2, bits));
+        writeln!(self.tw, "{}\t{}\t{}\t", offset, v1, v2)?;
+        Ok(())
+    }
+
+    fn range(&mut self, range: &DiffRange) -> eyre::Result<()> {
+        let len = range.len();
+        let (left, right) = (preview(len, &range.left), preview(len, &range.right));
+        let span = self.range_span(range.start, range.end);
+        let w = &mut self.tw;
+
+        match self.format {
+            ValueOutputFormat::Decimal => {
+                writeln!(w, "{}\t{}\t{}\t{}\t", span, range.len(), left, right)?
+            }
+            _ => writeln!(w, "{}\t{:x}\t{}\t{}\t", span, range.len(), left, right)?,
+        }
+        Ok(())
+    }
+
+    fn edit(&mut self, edit: &Edit) -> eyre::Result<()> {
+        let kind = edit.kind;
+        let w = &mut self.tw;
+
+        match self.format {
+            ValueOutputFormat::Decimal => writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}\t",
+                kind, edit.offset1, edit.len1, edit.offset2, edit.len2
+            )?,
+            _ => writeln!(
+                w,
+                "{}\t{:x}\t{:x}\t{:x}\t{:x}\t",
+                kind, edit.offset1, edit.len1, edit.offset2, edit.len2
+            )?,
+        }
+        Ok(())
+    }
+
+    fn multi(&mut self, difference: &MultiDifference) -> eyre::Result<()> {
+        let bits = difference.size * u8::BITS as usize;
+        let equal = match self.format {
+            ValueOutputFormat::Combined => ".\t.",
+            _ => ".",
+        };
+
+        let mut row = format!(
+            "{}\t{}\t",
+            self.offset(difference.offset),
+            self.value(difference.reference, bits)
+        );
+        for value in &difference.values {
+            match value {
+                Some(value) => row += &self.value(*value, bits),
+                None => row += equal,
+            }
+            row += "\t";
+        }
+        writeln!(self.tw, "{}", row)?;
+        Ok(())
+    }
+
+    fn unstable(&mut self, unstable: &UnstableByte) -> eyre::Result<()> {
+        let bits = u8::BITS as usize;
+        let equal = match self.format {
+            ValueOutputFormat::Combined => ".\t.",
+            _ => ".",
+        };
+
+        let mut row = format!(
+            "{}\t{}\t",
+            self.offset(unstable.offset),
+            self.value(unstable.vote as u64, bits)
+        );
+        for &value in &unstable.values {
+            match value == unstable.vote {
+                true => row += equal,
+                false => row += &self.value(value as u64, bits),
+            }
+            row += "\t";
+        }
+        writeln!(
+            self.tw,
+            "{}{}\t{}\t",
+            row,
+            self.value(unstable.bits as u64, bits),
+            format_confidence(unstable.confidence())
+        )?;
+        Ok(())
+    }
+
+    fn merge(&mut self, range: &MergeRange) -> eyre::Result<()> {
+        let len = range.len();
+        let (base, ours, theirs) = (
+            preview(len, &range.base),
+            preview(len, &range.ours),
+            preview(len, &range.theirs),
+        );
+        let span = self.range_span(range.start, range.end);
+        let len = match self.format {
+            ValueOutputFormat::Decimal => len.to_string(),
+            _ => format!("{:x}", len),
+        };
+
+        writeln!(
+            self.tw,
+            "{}\t{}\t{}\t{}\t{}\t{}\t",
+            range.kind, span, len, base, ours, theirs
+        )?;
+        Ok(())
+    }
+
+    fn tail(&mut self, tail: &Tail) -> eyre::Result<()> {
+        // Otherwise reported along with the statistics.
+        if !self.ranges {
+            return Ok(());
+        }
+        let (left, right) = tail_previews(tail);
+        let span = self.range_span(tail.start, tail.end);
+        let w = &mut self.tw;
+
+        match self.format {
+            ValueOutputFormat::Decimal => {
+                writeln!(w, "{}\t{}\t{}\t{}\t", span, tail.len(), left, right)?
+            }
+            _ => writeln!(w, "{}\t{:x}\t{}\t{}\t", span, tail.len(), left, right)?,
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> eyre::Result<()> {
+        if let Some(files) = &summary.files {
+            if !self.report_only {
+                writeln!(self.tw)?;
+            }
+            let offset = |offset: Option<u64>| match offset {
+                None => "-".to_string(),
+                Some(offset) => format_offset(offset, &self.format, &self.offsets),
+            };
+            writeln!(
+                self.tw,
+                "FILE\tDIFFERING WORDS\tFLIPPED BITS\tBIT ERROR RATE\tFIRST\tLAST\t"
+            )?;
+            for file in files {
+                let stats = &file.stats;
+                writeln!(
+                    self.tw,
+                    "{}\t{}\t{}\t{}\t{}\t{}\t",
+                    file.file,
+                    stats.differences,
+                    stats.flipped_bits,
+                    format_rate(file.bit_error_rate),
+                    offset(stats.first_offset),
+                    offset(stats.last_offset)
+                )?;
+            }
+        }
+        if self.stats {
+            for (label, value) in summary.report(&self.format, &self.offsets) {
+                writeln!(self.tw, "{:<20}{}", label, value)?;
+            }
+        } else if let Some(tail) = summary.tail.as_ref().filter(|_| !self.ranges) {
+            let tail = format_tail(tail, &self.format, &self.offsets);
+            writeln!(self.tw, "{:<20}{}", "tail", tail)?;
+        }
+        if self.histogram {
+            if self.stats {
+                writeln!(self.tw)?;
+            }
+            let histogram = &summary.stats.as_ref().unwrap().histogram;
+            writeln!(self.tw, "BIT\t0->1\t1->0\tTOTAL\t")?;
+            for (bit, (set, cleared)) in histogram.set.iter().zip(&histogram.cleared).enumerate() {
+                writeln!(
+                    self.tw,
+                    "{}\t{}\t{}\t{}\t",
+                    bit,
+                    set,
+                    cleared,
+                    set + cleared
+                )?;
+            }
+        }
+        self.tw.flush()?;
+        Ok(())
+    }
+}
+
+/// Builds the records of the structured outputs.
+struct Records {
+    value_type: Option<ValueType>,
+    offsets: Offsets,
+}
+
+impl Records {
+    fn new(args: &Args) -> Self {
+        Self {
+            value_type: args.value_type,
+            offsets: Offsets::new(args),
+        }
+    }
+
+    fn difference(&self, difference: &Difference) -> Record {
+        let typed = self
+            .value_type
+            .map(|value_type| typed(difference, value_type));
+
+        Record {
+            offset: difference.offset,
+            file2_offset: self.offsets.file2(difference.offset),
+            size: difference.size,
+            left: difference.left,
+            right: difference.right,
+            xor: difference.xor(),
+            bits: difference.flipped_bits().collect(),
+            left_value: typed.map(|(left, _, _)| left),
+            right_value: typed.map(|(_, right, _)| right),
+            delta: typed.map(|(_, _, delta)| delta),
+        }
+    }
+
+    fn multi(&self, difference: &MultiDifference) -> MultiRecord {
+        MultiRecord {
+            offset: difference.offset,
+            file2_offset: self.offsets.file2(difference.offset),
+            size: difference.size,
+            values: std::iter::once(Some(difference.reference))
+                .chain(difference.values.iter().copied())
+                .collect(),
+        }
+    }
+
+    fn unstable(&self, unstable: &UnstableByte) -> UnstableRecord {
+        UnstableRecord {
+            offset: unstable.offset,
+            vote: unstable.vote,
+            values: unstable.values.clone(),
+            bits: unstable.bits,
+            confidence: unstable.confidence(),
+        }
+    }
+
+    fn merge(&self, range: &MergeRange) -> MergeRecord {
+        MergeRecord {
+            kind: range.kind,
+            start: range.start,
+            end: range.end,
+            length: range.len(),
+            base: preview(range.len(), &range.base),
+            ours: preview(range.len(), &range.ours),
+            theirs: preview(range.len(), &range.theirs),
+        }
+    }
+
+    fn tail(&self, tail: &Tail) -> TailRecord {
+        let (left, right) = tail_previews(tail);
+        TailRecord {
+            file: tail.file,
+            start: tail.start,
+            end: tail.end,
+            file2_start: self.offsets.file2(tail.start),
+            length: tail.len(),
+            padding: tail.padding,
+            left,
+            right,
+        }
+    }
+
+    fn range(&self, range: &DiffRange) -> RangeRecord {
+        RangeRecord {
+            start: range.start,
+            end: range.end,
+            file2_start: self.offsets.file2(range.start),
+            length: range.len(),
+            differences: range.differences,
+            flipped_bits: range.flipped_bits,
+            left: preview(range.len(), &range.left),
+            right: preview(range.len(), &range.right),
+        }
+    }
+}
+
+/// JSON representation of a [`Difference`].
+#[derive(Serialize)]
+struct Record {
+    offset: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file2_offset: Option<u64>,
+    size: usize,
+    left: u64,
+    right: u64,
+    xor: u64,
+    bits: Vec<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    left_value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    right_value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<Value>,
+}
+
+/// JSON representation of a [`MultiDifference`].
+#[derive(Serialize)]
+struct MultiRecord {
+    offset: u64,
+    /// Offset in the other files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file2_offset: Option<u64>,
+    size: usize,
+    /// Value in each file, null where equal to the first one.
+    values: Vec<Option<u64>>,
+}
+
+/// JSON representation of an [`UnstableByte`].
+#[derive(Serialize)]
+struct UnstableRecord {
+    offset: u64,
+    vote: u8,
+    /// Value in each file.
+    values: Vec<u8>,
+    /// Bits on which the files disagree.
+    bits: u8,
+    confidence: f64,
+}
+
+/// JSON representation of a [`MergeRange`].
+#[derive(Serialize)]
+struct MergeRecord {
+    kind: MergeKind,
+    start: u64,
+    end: u64,
+    length: u64,
+    base: String,
+    ours: String,
+    theirs: String,
+}
+
+/// JSON representation of a [`DiffRange`].
+#[derive(Serialize)]
+struct RangeRecord {
+    start: u64,
+    end: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file2_start: Option<u64>,
+    length: u64,
+    differences: u64,
+    flipped_bits: u64,
+    left: String,
+    right: String,
+}
+
+/// JSON representation of a [`Tail`].
+#[derive(Serialize)]
+struct TailRecord {
+    /// The longer file, 1 or 2.
+    file: u8,
+    start: u64,
+    end: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file2_start: Option<u64>,
+    length: u64,
+    padding: Option<u8>,
+    left: String,
+    right: String,
+}
+
+/// `{"differences": [...], "summary": {...}}`, written as the comparison progresses.
+///
+/// The records are listed under `"ranges"` instead when grouping into ranges,
+/// `"edits"` when aligning, or `"unstable"` when voting.
+pub struct Json<W: Write> {
+    w: W,
+    key: &'static str,
+    records: Records,
+    ranges: bool,
+    count: u64,
+}
+
+impl<W: Write> Json<W> {
+    pub fn new(w: W, args: &Args) -> Self {
+        let key = if args.edits() {
+            "edits"
+        } else if args.vote.is_some() {
+            "unstable"
+        } else if args.ranges || args.three_way {
+            "ranges"
+        } else {
+            "differences"
+        };
+        Self {
+            w,
+            key,
+            records: Records::new(args),
+            ranges: args.ranges,
+            count: 0,
+        }
+    }
+
+    fn write_record<T: Serialize>(&mut self, record: &T) -> eyre::Result<()> {
+        match self.count {
+            0 => write!(self.w, "{{\"{}\":[", self.key)?,
+            _ => write!(self.w, ",")?,
+        }
+        serde_json::to_writer(&mut self.w, record)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+impl<W: Write> Output for Json<W> {
+    fn difference(&mut self, difference: &Difference) -> eyre::Result<()> {
+        self.write_record(&self.records.difference(difference))
+    }
+
+    fn range(&mut self, range: &DiffRange) -> eyre::Result<()> {
+        self.write_record(&self.records.range(range))
+    }
+
+    fn edit(&mut self, edit: &Edit) -> eyre::Result<()> {
+        self.write_record(edit)
+    }
+
+    fn multi(&mut self, difference: &MultiDifference) -> eyre::Result<()> {
+        self.write_record(&self.records.multi(difference))
+    }
+
+    fn unstable(&mut self, unstable: &UnstableByte) -> eyre::Result<()> {
+        self.write_record(&self.records.unstable(unstable))
+    }
+
+    fn merge(&mut self, range: &MergeRange) -> eyre::Result<()> {
+        self.write_record(&self.records.merge(range))
+    }
+
+    fn tail(&mut self, tail: &Tail) -> eyre::Result<()> {
+        // Otherwise reported in the summary only.
+        match self.ranges {
+            true => self.write_record(&self.records.tail(tail)),
+            false => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, summary: &Summary) -> eyre::Result<()> {
+        if self.count == 0 {
+            write!(self.w, "{{\"{}\":[", self.key)?;
+        }
+        write!(self.w, "],\"summary\":")?;
+        serde_json::to_writer(&mut self.w, summary)?;
+        writeln!(self.w, "}}")?;
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+/// One `{"type": ...}` object per line, the summary being the last one.
+pub struct Ndjson<W: Write> {
+    w: W,
+    records: Records,
+}
+
+impl<W: Write> Ndjson<W> {
+    pub fn new(w: W, args: &Args) -> Self {
+        Self {
+            w,
+            records: Records::new(args),
+        }
+    }
+
+    fn write_line<T: Serialize>(&mut self, kind: &str, value: &T) -> eyre::Result<()> {
+        #[derive(Serialize)]
+        struct Line<'a, T> {
+            #[serde(rename = "type")]
+            kind: &'a str,
+            #[serde(flatten)]
+            value: &'a T,
+        }
+
+        serde_json::to_writer(&mut self.w, &Line { kind, value })?;
+        writeln!(self.w)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Output for Ndjson<W> {
+    fn difference(&mut self, difference: &Difference) -> eyre::Result<()> {
+        let record = self.records.difference(difference);
+        self.write_line("difference", &record)
+    }
+
+    fn range(&mut self, range: &DiffRange) -> eyre::Result<()> {
+        let record = self.records.range(range);
+        self.write_line("range", &record)
+    }
+
+    fn edit(&mut self, edit: &Edit) -> eyre::Result<()> {
+        self.write_line("edit", edit)
+    }
+
+    fn multi(&mut self, difference: &MultiDifference) -> eyre::Result<()> {
+        let record = self.records.multi(difference);
+        self.write_line("difference", &record)
+    }
+
+    fn unstable(&mut self, unstable: &UnstableByte) -> eyre::Result<()> {
+        let record = self.records.unstable(unstable);
+        self.write_line("unstable", &record)
+    }
+
+    fn merge(&mut self, range: &MergeRange) -> eyre::Result<()> {
+        let record = self.records.merge(range);
+        self.write_line("range", &record)
+    }
+
+    fn tail(&mut self, tail: &Tail) -> eyre::Result<()> {
+        let record = self.records.tail(tail);
+        self.write_line("tail", &record)
+    }
+
+    fn finish(&mut self, summary: &Summary) -> eyre::Result<()> {
+        self.write_line("summary", summary)?;
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+/// Delimited rows with a fixed header, see the README for the column schema.
+///
+/// With `--stats` or `--histogram`, the rows are `statistic,value` pairs instead.
+pub struct Csv<W: Write> {
+    w: csv::Writer<W>,
+    records: Records,
+    ranges: bool,
+    /// Whether the rows of differences have a `length` column, for that of the tail.
+    lengths: bool,
+    stats: bool,
+    histogram: bool,
+}
+
+impl<W: Write> Csv<W> {
+    const HEADER: [&'static str; 6] = [
+        "offset",
+        "file1",
+        "file2",
+        "xor",
+        "flipped_bits",
+        "bit_positions",
+    ];
+
+    /// Appended to [`Self::HEADER`] with `--as`.
+    const TYPED_HEADER: [&'static str; 3] = ["file1_value", "file2_value", "delta"];
+
+    const RANGES_HEADER: [&'static str; 7] = [
+        "start",
+        "end",
+        "length",
+        "differences",
+        "flipped_bits",
+        "file1_preview",
+        "file2_preview",
+    ];
+
+    const EDITS_HEADER: [&'static str; 5] = ["kind", "offset1", "length1", "offset2", "length2"];
+
+    const MERGE_HEADER: [&'static str; 7] = [
+        "kind",
+        "start",
+        "end",
+        "length",
+        "base_preview",
+        "ours_preview",
+        "theirs_preview",
+    ];
+
+    pub fn new(w: W, delimiter: u8, args: &Args) -> eyre::Result<Self> {
+        let mut w = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(w);
+        let records = Records::new(args);
+        let lengths = args.tail && !args.ranges && !args.report_only();
+
+        let files: Vec<String> = (1..=args.others.len() + 2)
+            .map(|i| format!("file{}", i))
+            .collect();
+        let mut header = if args.report_only() {
+            vec!["statistic", "value"]
+        } else if args.edits() {
+            Self::EDITS_HEADER.to_vec()
+        } else if args.three_way {
+            Self::MERGE_HEADER.to_vec()
+        } else if args.vote.is_some() {
+            ["offset", "vote"]
+                .into_iter()
+                .chain(files.iter().map(String::as_str))
+                .chain(["bits", "confidence"])
+                .collect()
+        } else if args.multi() {
+            std::iter::once("offset")
+                .chain(files.iter().map(String::as_str))
+                .collect()
+        } else if args.ranges {
+            Self::RANGES_HEADER.to_vec()
+        } else if args.value_type.is_some() {
+            [&Self::HEADER[..], &Self::TYPED_HEADER].concat()
+        } else {
+            Self::HEADER.to_vec()
+        };
+        if lengths {
+            header.push("length");
+        }
+        if records.offsets.split() && !args.report_only() && !args.edits() {
+            header.push(if args.ranges {
+                "file2_start"
+            } else {
+                "file2_offset"
+            });
+        }
+        w.write_record(header)?;
+
+        Ok(Self {
+            w,
+            records,
+            ranges: args.ranges,
+            lengths,
+            stats: args.stats,
+            histogram: args.histogram,
+        })
+    }
+}
+
+impl<W: Write> Output for Csv<W> {
+    fn difference(&mut self, difference: &Difference) -> eyre::Result<()> {
+        let record = self.records.difference(difference);
+        let bits: Vec<String> = record.bits.iter().map(|bit| bit.to_string()).collect();
+
+        let mut row = vec![
+            record.offset.to_string(),
+            record.left.to_string(),
+            record.right.to_string(),
+            record.xor.to_string(),
+            record.bits.len().to_string(),
+            bits.join(" "),
+        ];
+        if let (Some(left), Some(right), Some(delta)) =
+            (record.left_value, record.right_value, record.delta)
+        {
+            row.extend([left.to_string(), right.to_string(), delta.to_string()]);
+        }
+        if self.lengths {
+            row.push(record.size.to_string());
+        }
+        row.extend(record.file2_offset.map(|offset| offset.to_string()));
+
+        self.w.write_record(row)?;
+        Ok(())
+    }
+
+    fn range(&mut self, range: &DiffRange) -> eyre::Result<()> {
+        let record = self.records.range(range);
+
+        let mut row = vec![
+            record.start.to_string(),
+            record.end.to_string(),
+            record.length.to_string(),
+            record.differences.to_string(),
+            record.flipped_bits.to_string(),
+            record.left,
+            record.right,
+        ];
+        row.extend(record.file2_start.map(|start| start.to_string()));
+
+        self.w.write_record(row)?;
+        Ok(())
+    }
+
+    fn edit(&mut self, edit: &Edit) -> eyre::Result<()> {
+        self.w.write_record([
+            edit.kind.to_string(),
+            edit.offset1.to_string(),
+            edit.len1.to_string(),
+            edit.offset2.to_string(),
+            edit.len2.to_string(),
+        ])?;
+        Ok(())
+    }
+
+    fn multi(&mut self, difference: &MultiDifference) -> eyre::Result<()> {
+        let record = self.records.multi(difference);
+
+        let mut row = vec![record.offset.to_string()];
+        row.extend(
+            record
+                .values
+                .iter()
+                .map(|value| value.map(|value| value.to_string()).unwrap_or_default()),
+        );
+        row.extend(record.file2_offset.map(|offset| offset.to_string()));
+
+        self.w.write_record(row)?;
+        Ok(())
+    }
+
+    fn merge(&mut self, range: &MergeRange) -> eyre::Result<()> {
+        let record = self.records.merge(range);
+
+        self.w.write_record([
+            record.kind.to_string(),
+            record.start.to_string(),
+            record.end.to_string(),
+            record.length.to_string(),
+            record.base,
+            record.ours,
+            record.theirs,
+        ])?;
+        Ok(())
+    }
+
+    fn unstable(&mut self, unstable: &UnstableByte) -> eyre::Result<()> {
+        let record = self.records.unstable(unstable);
+
+        let mut row = vec![record.offset.to_string(), record.vote.to_string()];
+        row.extend(record.values.iter().map(|value| value.to_string()));
+        row.extend([record.bits.to_string(), record.confidence.to_string()]);
+
+        self.w.write_record(row)?;
+        Ok(())
+    }
+
+    fn tail(&mut self, tail: &Tail) -> eyre::Result<()> {
+        let record = self.records.tail(tail);
+
+        let mut row = if self.ranges {
+            vec![
+                record.start.to_string(),
+                record.end.to_string(),
+                record.length.to_string(),
+                String::new(),
+                String::new(),
+                record.left,
+                record.right,
+            ]
+        } else {
+            // The first byte of the longer file, as a difference from nothing.
+            let first = tail.preview.first().map(|b| b.to_string());
+            let (left, right) = match tail.file {
+                1 => (first, None),
+                _ => (None, first),
+            };
+            let mut row = vec![
+                record.start.to_string(),
+                left.unwrap_or_default(),
+                right.unwrap_or_default(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ];
+            if self.records.value_type.is_some() {
+                row.extend([String::new(), String::new(), String::new()]);
+            }
+            row.push(record.length.to_string());
+            row
+        };
+        row.extend(record.file2_start.map(|start| start.to_string()));
+
+        self.w.write_record(row)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &Summary) -> eyre::Result<()> {
+        if self.stats {
+            for (label, value) in summary.report(&ValueOutputFormat::Decimal, &self.records.offsets)
+            {
+                self.w.write_record([label, &value])?;
+            }
+            for file in summary.files.iter().flatten() {
+                let report = report_stats(
+                    &file.stats,
+                    summary.masked,
+                    &ValueOutputFormat::Decimal,
+                    &self.records.offsets,
+                );
+                for (label, value) in report {
+                    self.w
+                        .write_record([format!("{}: {}", file.file, label), value])?;
+                }
+            }
+        }
+        if self.histogram {
+            let histogram = &summary.stats.as_ref().unwrap().histogram;
+            for (bit, (set, cleared)) in histogram.set.iter().zip(&histogram.cleared).enumerate() {
+                self.w
+                    .write_record([format!("bit {} 0->1", bit), set.to_string()])?;
+                self.w
+                    .write_record([format!("bit {} 1->0", bit), cleared.to_string()])?;
+            }
+        }
+        self.w.flush()?;
+        Ok(())
+    }
+}
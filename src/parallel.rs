@@ -0,0 +1,258 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{cmp::Ordering, collections::VecDeque, io, ops::Range};
+
+use rayon::prelude::*;
+
+use crate::{DiffRange, Differ, Difference, Stats, Tail, PREVIEW_SIZE};
+
+/// Size of the chunks compared in parallel.
+pub const CHUNK_SIZE: usize = 1 << 18;
+
+/// Parallel comparison of sources held in memory, see [`Differ::parallel`].
+///
+/// The sources are split into chunks of [`CHUNK_SIZE`] bytes, a batch of
+/// which is compared across threads at a time. Equal chunks are skipped as a
+/// whole, and the differences of the others are merged back in offset order,
+/// the same as those of a sequential comparison.
+pub struct ParDiffer<'a> {
+    /// Settings of the comparison of each chunk.
+    differ: Differ<&'a [u8], &'a [u8]>,
+    left: &'a [u8],
+    right: &'a [u8],
+    /// Offset of the first compared byte.
+    start: u64,
+    /// Position of the next chunk within the sources.
+    pos: usize,
+    pad: Option<u8>,
+    pending: VecDeque<Difference>,
+    stats: Stats,
+}
+
+impl<'a> ParDiffer<'a> {
+    pub(crate) fn new(differ: Differ<&'a [u8], &'a [u8]>) -> Self {
+        let (left, right) = differ.sources();
+        let start = differ.start();
+        let stats = differ.stats();
+        let pad = differ.padding();
+        Self {
+            differ,
+            left,
+            right,
+            start,
+            pos: 0,
+            pad,
+            pending: VecDeque::new(),
+            stats,
+        }
+    }
+
+    /// See [`Differ::eof_ordering`].
+    pub fn eof_ordering(&self) -> Option<Ordering> {
+        self.is_done()
+            .then(|| self.left.len().cmp(&self.right.len()))
+    }
+
+    /// See [`Differ::stats`].
+    pub fn stats(&self) -> Stats {
+        self.stats.clone()
+    }
+
+    /// See [`Differ::tail`].
+    pub fn tail(&self) -> Option<Tail> {
+        if self.pad.is_some() || !self.is_done() {
+            return None;
+        }
+        let (file, longer) = match self.left.len().cmp(&self.right.len()) {
+            Ordering::Greater => (1, self.left),
+            Ordering::Less => (2, self.right),
+            Ordering::Equal => return None,
+        };
+        let len = self.len();
+        let mut tail = Tail::new(file, self.start + len as u64);
+        tail.add(&longer[len..]);
+        Some(tail)
+    }
+
+    /// See [`Differ::ranges`].
+    pub fn ranges(self, gap: u64) -> ParRanges<'a> {
+        ParRanges {
+            differ: self,
+            gap,
+            current: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Number of bytes to compare.
+    fn len(&self) -> usize {
+        match self.pad {
+            Some(_) => std::cmp::max(self.left.len(), self.right.len()),
+            None => std::cmp::min(self.left.len(), self.right.len()),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos >= self.len()
+    }
+
+    /// Compare the next batch of chunks, one per thread, with `job`.
+    fn batch<T: Default + Send>(
+        &mut self,
+        job: impl Fn(Differ<&'a [u8], &'a [u8]>) -> io::Result<(T, Stats)> + Sync,
+    ) -> io::Result<Vec<T>> {
+        let len = self.len();
+        let chunks: Vec<Range<usize>> = (self.pos..len)
+            .step_by(CHUNK_SIZE)
+            .take(rayon::current_num_threads())
+            .map(|start| start..std::cmp::min(start + CHUNK_SIZE, len))
+            .collect();
+        self.pos = chunks.last().map_or(len, |chunk| chunk.end);
+
+        let (differ, left, right, start) = (&self.differ, self.left, self.right, self.start);
+        let results = chunks
+            .into_par_iter()
+            .map(|chunk| {
+                // Either source may end within the chunk when padding.
+                let (l, r) = (clamp(left, &chunk), clamp(right, &chunk));
+                let differ = differ.fork(l, r, start + chunk.start as u64);
+                if l == r {
+                    let stats = Stats {
+                        compared: chunk.len() as u64,
+                        ..differ.stats()
+                    };
+                    return Ok((T::default(), stats));
+                }
+                job(differ)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(results
+            .into_iter()
+            .map(|(result, stats)| {
+                self.stats.merge(&stats);
+                result
+            })
+            .collect())
+    }
+
+    /// Up to [`PREVIEW_SIZE`] bytes of both sources from `start`, without going past `end`.
+    fn preview(&self, start: u64, end: u64) -> (Vec<u8>, Vec<u8>) {
+        let end = std::cmp::min(end, start + PREVIEW_SIZE as u64);
+        let range = (start - self.start) as usize..(end - self.start) as usize;
+        let fill = self.pad.unwrap_or_default();
+        let bytes = |source: &[u8]| {
+            range
+                .clone()
+                .map(|i| source.get(i).copied().unwrap_or(fill))
+                .collect()
+        };
+        (bytes(self.left), bytes(self.right))
+    }
+
+    fn next_difference(&mut self) -> io::Result<Option<Difference>> {
+        while self.pending.is_empty() && !self.is_done() {
+            let batch = self.batch(|mut differ| {
+                let differences = (&mut differ).collect::<io::Result<Vec<_>>>()?;
+                Ok((differences, differ.stats()))
+            })?;
+            self.pending.extend(batch.into_iter().flatten());
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+impl Iterator for ParDiffer<'_> {
+    type Item = io::Result<Difference>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_difference().transpose()
+    }
+}
+
+/// The part of `source` within `range`.
+fn clamp<'a>(source: &'a [u8], range: &Range<usize>) -> &'a [u8] {
+    let len = source.len();
+    &source[std::cmp::min(range.start, len)..std::cmp::min(range.end, len)]
+}
+
+/// Iterator over the [`DiffRange`]s of a [`ParDiffer`], see [`ParDiffer::ranges`].
+///
+/// Ranges of consecutive chunks no more than the gap apart are merged.
+pub struct ParRanges<'a> {
+    differ: ParDiffer<'a>,
+    gap: u64,
+    /// Last range found, which may continue in the next chunks.
+    current: Option<DiffRange>,
+    pending: VecDeque<DiffRange>,
+}
+
+impl ParRanges<'_> {
+    /// See [`Differ::eof_ordering`].
+    pub fn eof_ordering(&self) -> Option<Ordering> {
+        self.differ.eof_ordering()
+    }
+
+    /// See [`Differ::stats`].
+    pub fn stats(&self) -> Stats {
+        self.differ.stats()
+    }
+
+    /// See [`Differ::tail`].
+    pub fn tail(&self) -> Option<Tail> {
+        self.differ.tail()
+    }
+
+    fn next_range(&mut self) -> io::Result<Option<DiffRange>> {
+        while self.pending.is_empty() && !self.differ.is_done() {
+            let gap = self.gap;
+            let batch = self.differ.batch(|differ| {
+                let mut ranges = differ.ranges(gap);
+                let found = (&mut ranges).collect::<io::Result<Vec<_>>>()?;
+                Ok((found, ranges.stats()))
+            })?;
+
+            for range in batch.into_iter().flatten() {
+                match &mut self.current {
+                    Some(current) if range.start - current.end <= self.gap => {
+                        current.end = range.end;
+                        current.differences += range.differences;
+                        current.flipped_bits += range.flipped_bits;
+                        (current.left, current.right) =
+                            self.differ.preview(current.start, current.end);
+                    }
+                    _ => self.pending.extend(self.current.replace(range)),
+                }
+            }
+        }
+
+        match self.pending.pop_front() {
+            Some(range) => Ok(Some(range)),
+            None => Ok(self.current.take()),
+        }
+    }
+}
+
+impl Iterator for ParRanges<'_> {
+    type Item = io::Result<DiffRange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_range().transpose()
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use crate::mask::parse_hex;
+
+/// Endless repetition of a few bytes, such as `ff` for erased flash, to
+/// compare a source against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    /// Position of the next byte within the pattern.
+    pos: usize,
+}
+
+/// A pattern that could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsePatternError {
+    pub message: String,
+}
+
+impl fmt::Display for ParsePatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParsePatternError {}
+
+impl Pattern {
+    /// # Panics
+    ///
+    /// If `bytes` is empty.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        assert!(!bytes.is_empty());
+        Self { bytes, pos: 0 }
+    }
+
+    /// Parse pairs of hex digits, e.g. `55aa` for 0x55, 0xaa, 0x55, ...
+    pub fn parse(s: &str) -> Result<Self, ParsePatternError> {
+        let bytes = parse_hex(s, "pattern").map_err(|message| ParsePatternError { message })?;
+        Ok(Self::new(bytes))
+    }
+
+    /// Start from `offset` within the repetition, so that the pattern stays
+    /// in phase with a source compared from that offset.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.pos = (offset % self.bytes.len() as u64) as usize;
+        self
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Read for Pattern {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for b in buf.iter_mut() {
+            *b = self.bytes[self.pos];
+            self.pos = (self.pos + 1) % self.bytes.len();
+        }
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `Pattern::bytes`, not `Read::bytes`.
+    fn parsed(s: &str) -> Vec<u8> {
+        Pattern::bytes(&Pattern::parse(s).unwrap()).to_vec()
+    }
+
+    fn read(mut pattern: Pattern, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        pattern.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parsed("ff"), [0xff]);
+        assert_eq!(parsed("55aA"), [0x55, 0xaa]);
+        assert_eq!(parsed("0x0102"), [1, 2]);
+        assert_eq!(parsed("0XdeadBEEF"), [0xde, 0xad, 0xbe, 0xef]);
+    }
+
+    #[test]
+    fn malformed() {
+        for s in ["", "0x", "f", "fff", "xy", "0x0x00", "é0", "ff ff"] {
+            let e = Pattern::parse(s).unwrap_err();
+            assert!(e.message.starts_with("invalid pattern"), "{:?}: {}", s, e);
+        }
+    }
+
+    #[test]
+    fn repeat() {
+        let pattern = Pattern::parse("010203").unwrap();
+
+        assert_eq!(read(pattern.clone(), 7), [1, 2, 3, 1, 2, 3, 1]);
+        assert_eq!(read(pattern.clone().offset(4), 4), [2, 3, 1, 2]);
+        assert_eq!(read(pattern.offset(3), 2), [1, 2]);
+    }
+}
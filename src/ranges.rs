@@ -0,0 +1,223 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    cmp::Ordering,
+    io::{self, Read},
+};
+
+use crate::{Differ, Stats, Tail};
+
+/// Number of bytes of each source kept in a [`DiffRange`].
+pub const PREVIEW_SIZE: usize = 8;
+
+/// A run of differences.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffRange {
+    /// Offset of the first differing byte.
+    pub start: u64,
+    /// Offset following the last differing byte.
+    pub end: u64,
+    /// Number of differing words within the range.
+    pub differences: u64,
+    /// Number of differing bits within the range.
+    pub flipped_bits: u64,
+    /// Up to [`PREVIEW_SIZE`] bytes of the first source from `start`.
+    pub left: Vec<u8>,
+    /// Up to [`PREVIEW_SIZE`] bytes of the second source from `start`.
+    pub right: Vec<u8>,
+}
+
+impl DiffRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn push(&mut self, v1: u8, v2: u8) {
+        if self.left.len() < PREVIEW_SIZE {
+            self.left.push(v1);
+            self.right.push(v2);
+        }
+    }
+}
+
+/// Iterator over the [`DiffRange`]s of a [`Differ`], see [`Differ::ranges`].
+pub struct Ranges<R1, R2> {
+    differ: Differ<R1, R2>,
+    gap: u64,
+    current: Option<DiffRange>,
+    /// Bytes following the current range, kept until it is either extended or done.
+    pending: Vec<(u8, u8)>,
+}
+
+impl<R1: Read, R2: Read> Ranges<R1, R2> {
+    pub(crate) fn new(differ: Differ<R1, R2>, gap: u64) -> Self {
+        Self {
+            differ,
+            gap,
+            current: None,
+            pending: Vec::with_capacity(PREVIEW_SIZE),
+        }
+    }
+
+    /// See [`Differ::eof_ordering`].
+    pub fn eof_ordering(&self) -> Option<Ordering> {
+        self.differ.eof_ordering()
+    }
+
+    /// See [`Differ::stats`].
+    pub fn stats(&self) -> Stats {
+        self.differ.stats()
+    }
+
+    /// See [`Differ::tail`].
+    pub fn tail(self) -> io::Result<Option<Tail>> {
+        self.differ.tail()
+    }
+
+    /// See [`Differ::compared`].
+    pub fn compared(&self) -> u64 {
+        self.differ.compared()
+    }
+
+    fn next_range(&mut self) -> io::Result<Option<DiffRange>> {
+        while let Some((offset, word)) = self.differ.next_word()? {
+            let difference = self.differ.compare(offset, word.clone());
+            let (w1, w2) = self.differ.words(word);
+
+            if let Some(difference) = difference {
+                let range = self.current.get_or_insert_with(|| DiffRange {
+                    start: offset,
+                    end: offset,
+                    differences: 0,
+                    flipped_bits: 0,
+                    left: Vec::with_capacity(PREVIEW_SIZE),
+                    right: Vec::with_capacity(PREVIEW_SIZE),
+                });
+
+                for (p1, p2) in self.pending.drain(..) {
+                    range.push(p1, p2);
+                }
+                for (v1, v2) in w1.iter().zip(w2) {
+                    range.push(*v1, *v2);
+                }
+                range.end = offset + w1.len() as u64;
+                range.differences += 1;
+                range.flipped_bits += difference.xor().count_ones() as u64;
+            } else if let Some(range) = &self.current {
+                if offset + w1.len() as u64 - range.end > self.gap {
+                    self.pending.clear();
+                    return Ok(self.current.take());
+                }
+                for (v1, v2) in w1.iter().zip(w2) {
+                    if range.left.len() + self.pending.len() < PREVIEW_SIZE {
+                        self.pending.push((*v1, *v2));
+                    }
+                }
+            }
+        }
+
+        self.pending.clear();
+        Ok(self.current.take())
+    }
+}
+
+impl<R1: Read, R2: Read> Iterator for Ranges<R1, R2> {
+    type Item = io::Result<DiffRange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_range().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(left: &[u8], right: &[u8], gap: u64) -> Vec<DiffRange> {
+        Differ::new(left, right)
+            .ranges(gap)
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    fn spans(ranges: &[DiffRange]) -> Vec<(u64, u64, u64)> {
+        ranges
+            .iter()
+            .map(|range| (range.start, range.end, range.differences))
+            .collect()
+    }
+
+    #[test]
+    fn gap() {
+        let left = [0u8; 32];
+        let mut right = left;
+        for i in [2, 3, 6, 20] {
+            right[i] = 0x11;
+        }
+
+        // Bytes 4 and 5 are equal, so 6 joins 2..4 only with a gap of 2.
+        assert_eq!(
+            spans(&ranges(&left, &right, 0)),
+            [(2, 4, 2), (6, 7, 1), (20, 21, 1)]
+        );
+        assert_eq!(
+            spans(&ranges(&left, &right, 1)),
+            [(2, 4, 2), (6, 7, 1), (20, 21, 1)]
+        );
+        let found = ranges(&left, &right, 2);
+        assert_eq!(spans(&found), [(2, 7, 3), (20, 21, 1)]);
+        assert_eq!(found[0].flipped_bits, 6);
+        assert_eq!(found[0].left, [0, 0, 0, 0, 0]);
+        assert_eq!(found[0].right, [0x11, 0x11, 0, 0, 0x11]);
+        assert_eq!(spans(&ranges(&left, &right, 13)), [(2, 21, 4)]);
+    }
+
+    #[test]
+    fn preview() {
+        let left = [0u8; 32];
+        let mut right = left;
+        right[0] = 1;
+        right[15] = 2;
+
+        let found = ranges(&left, &right, 20);
+        assert_eq!(spans(&found), [(0, 16, 2)]);
+        assert_eq!(found[0].left, [0; PREVIEW_SIZE]);
+        assert_eq!(found[0].right, [1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn words() {
+        let left = [0u8; 16];
+        let mut right = left;
+        right[1] = 1;
+        right[9] = 1;
+
+        // Words 0..4 and 8..12 differ, with the word 4..8 equal between them.
+        let found: Vec<_> = Differ::new(&left[..], &right[..])
+            .word_size(4)
+            .ranges(4)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(spans(&found), [(0, 12, 2)]);
+    }
+}
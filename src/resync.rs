@@ -0,0 +1,350 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read},
+};
+
+use crate::{differ::read_full, Edit, EditKind, BUFFER_SIZE};
+
+/// Number of equal bytes which establish that both sources are aligned.
+pub const ANCHOR_SIZE: usize = 16;
+
+/// Streaming comparison which recovers from inserted or deleted bytes.
+///
+/// Both sources are compared offset by offset, as with [`Differ`](crate::Differ),
+/// until a run of differences suggests that they are no longer aligned. The
+/// next bytes of the first source are then searched for, with a rolling hash,
+/// within a window of the second one. Finding them tells the shift between
+/// both sources, and the comparison resumes from there.
+///
+/// Unlike [`align`](crate::align), only the window is held in memory, but
+/// edits larger than the window are reported as replacements.
+pub struct Resync<R1, R2> {
+    s1: Lookahead<R1>,
+    s2: Lookahead<R2>,
+    pos1: u64,
+    pos2: u64,
+    run: Option<Run>,
+    /// Consecutive equal bytes since the last difference of the run.
+    equal: usize,
+    /// Differences to skip before searching again, after a failed search.
+    backoff: u64,
+    window: usize,
+    threshold: u64,
+    done: bool,
+}
+
+/// A run of differences, possibly due to a shift.
+struct Run {
+    start1: u64,
+    start2: u64,
+    /// Offsets following the last difference.
+    end1: u64,
+    end2: u64,
+    differences: u64,
+    /// Number of differences at which to search for an anchor.
+    search_at: u64,
+}
+
+impl<R1: Read, R2: Read> Resync<R1, R2> {
+    pub fn new(r1: R1, r2: R2) -> Self {
+        Self {
+            s1: Lookahead::new(r1),
+            s2: Lookahead::new(r2),
+            pos1: 0,
+            pos2: 0,
+            run: None,
+            equal: 0,
+            backoff: 0,
+            window: 1 << 20,
+            threshold: 32,
+            done: false,
+        }
+    }
+
+    /// Bytes of each source searched for an anchor, 1 MiB by default.
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Number of differences after which both sources are considered out of
+    /// alignment, 32 by default.
+    pub fn threshold(mut self, threshold: u64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    fn next_edit(&mut self) -> io::Result<Option<Edit>> {
+        while !self.done {
+            self.s1.fill(self.pos1 + 1)?;
+            self.s2.fill(self.pos2 + 1)?;
+
+            if self.pos1 >= self.s1.end() || self.pos2 >= self.s2.end() {
+                self.done = true;
+
+                // Whatever is left of the longer source is part of the last edit.
+                let end1 = self.s1.read_to_end()?;
+                let end2 = self.s2.read_to_end()?;
+                let (start1, start2) = match self.run.take() {
+                    Some(run) => (run.start1, run.start2),
+                    None => (self.pos1, self.pos2),
+                };
+                return Ok(edit(start1, end1, start2, end2));
+            }
+
+            let v1 = self.s1.get(self.pos1);
+            let v2 = self.s2.get(self.pos2);
+            self.pos1 += 1;
+            self.pos2 += 1;
+
+            if v1 == v2 {
+                match &self.run {
+                    Some(_) => {
+                        self.equal += 1;
+                        if self.equal >= ANCHOR_SIZE {
+                            let run = self.close_run();
+                            return Ok(edit(run.start1, run.end1, run.start2, run.end2));
+                        }
+                    }
+                    None => {
+                        self.s1.discard(self.pos1);
+                        self.s2.discard(self.pos2);
+                    }
+                }
+                continue;
+            }
+
+            self.equal = 0;
+            let search_at = self.threshold + self.backoff;
+            let run = self.run.get_or_insert(Run {
+                start1: self.pos1 - 1,
+                start2: self.pos2 - 1,
+                end1: 0,
+                end2: 0,
+                differences: 0,
+                search_at,
+            });
+            run.end1 = self.pos1;
+            run.end2 = self.pos2;
+            run.differences += 1;
+            if run.differences < run.search_at {
+                continue;
+            }
+
+            let (start1, start2) = (run.start1, run.start2);
+            match self.search(start1, start2)? {
+                Some((anchor1, anchor2)) => {
+                    self.pos1 = anchor1;
+                    self.pos2 = anchor2;
+                    self.close_run();
+                    self.backoff = 0;
+                    return Ok(edit(start1, anchor1, start2, anchor2));
+                }
+                None => {
+                    // Most likely a block replaced altogether: report it so far,
+                    // and try again only once past another window.
+                    self.close_run();
+                    self.backoff = self.window as u64;
+                    return Ok(edit(start1, self.pos1, start2, self.pos2));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn close_run(&mut self) -> Run {
+        self.equal = 0;
+        self.s1.discard(self.pos1);
+        self.s2.discard(self.pos2);
+        self.run.take().expect("no run to close")
+    }
+
+    /// Find the earliest anchor at or after `start1` in the first source,
+    /// within the window of the second source from `start2`.
+    ///
+    /// When the anchor occurs several times, the current alignment is
+    /// preferred, then the first occurrence.
+    fn search(&mut self, start1: u64, start2: u64) -> io::Result<Option<(u64, u64)>> {
+        let span = (self.window + ANCHOR_SIZE) as u64;
+        self.s1.fill(start1 + span)?;
+        self.s2.fill(start2 + span)?;
+
+        let a = self.s1.slice(start1, self.window + ANCHOR_SIZE);
+        let b = self.s2.slice(start2, self.window + ANCHOR_SIZE);
+        if a.len() < ANCHOR_SIZE || b.len() < ANCHOR_SIZE {
+            return Ok(None);
+        }
+
+        let mut index = HashMap::with_capacity(b.len() - ANCHOR_SIZE + 1);
+        let mut hash = RollingHash::new(&b[..ANCHOR_SIZE]);
+        for j in 0..=b.len() - ANCHOR_SIZE {
+            if j > 0 {
+                hash.roll(b[j - 1], b[j + ANCHOR_SIZE - 1]);
+            }
+            index.entry(hash.value()).or_insert(j);
+        }
+
+        let anchor = |i: usize, j: usize| {
+            j + ANCHOR_SIZE <= b.len() && a[i..i + ANCHOR_SIZE] == b[j..j + ANCHOR_SIZE]
+        };
+
+        let mut hash = RollingHash::new(&a[..ANCHOR_SIZE]);
+        for i in 0..=a.len() - ANCHOR_SIZE {
+            if i > 0 {
+                hash.roll(a[i - 1], a[i + ANCHOR_SIZE - 1]);
+            }
+            let Some(&j) = index.get(&hash.value()) else {
+                continue;
+            };
+
+            if anchor(i, i) {
+                return Ok(Some((start1 + i as u64, start2 + i as u64)));
+            }
+            if anchor(i, j) {
+                return Ok(Some((start1 + i as u64, start2 + j as u64)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R1: Read, R2: Read> Iterator for Resync<R1, R2> {
+    type Item = io::Result<Edit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_edit().transpose()
+    }
+}
+
+/// Edit turning `start1..end1` of the first source into `start2..end2` of the second one.
+fn edit(start1: u64, end1: u64, start2: u64, end2: u64) -> Option<Edit> {
+    let (len1, len2) = (end1 - start1, end2 - start2);
+    let kind = match (len1, len2) {
+        (0, 0) => return None,
+        (0, _) => EditKind::Insert,
+        (_, 0) => EditKind::Delete,
+        _ => EditKind::Replace,
+    };
+
+    Some(Edit {
+        kind,
+        offset1: start1,
+        len1,
+        offset2: start2,
+        len2,
+    })
+}
+
+/// Bytes read ahead from a source, addressed by offset.
+struct Lookahead<R> {
+    r: R,
+    buffer: VecDeque<u8>,
+    /// Offset of the first buffered byte.
+    base: u64,
+    eof: bool,
+}
+
+impl<R: Read> Lookahead<R> {
+    fn new(r: R) -> Self {
+        Self {
+            r,
+            buffer: VecDeque::new(),
+            base: 0,
+            eof: false,
+        }
+    }
+
+    /// Offset following the last buffered byte.
+    fn end(&self) -> u64 {
+        self.base + self.buffer.len() as u64
+    }
+
+    /// Buffer the bytes up to `end`, unless the source ends first.
+    fn fill(&mut self, end: u64) -> io::Result<()> {
+        let mut chunk = [0u8; BUFFER_SIZE];
+        while !self.eof && self.end() < end {
+            let n = read_full(&mut self.r, &mut chunk)?;
+            self.buffer.extend(&chunk[..n]);
+            self.eof = n == 0;
+        }
+        Ok(())
+    }
+
+    fn get(&self, offset: u64) -> u8 {
+        self.buffer[(offset - self.base) as usize]
+    }
+
+    /// Up to `len` buffered bytes from `offset`.
+    fn slice(&mut self, offset: u64, len: usize) -> &[u8] {
+        let start = (offset - self.base) as usize;
+        let end = std::cmp::min(start + len, self.buffer.len());
+        &self.buffer.make_contiguous()[start..end]
+    }
+
+    /// Drop the bytes before `offset`, once there are enough of them.
+    fn discard(&mut self, offset: u64) {
+        let n = (offset - self.base) as usize;
+        if n >= BUFFER_SIZE {
+            self.buffer.drain(..n);
+            self.base = offset;
+        }
+    }
+
+    /// Read the rest of the source, returning its length.
+    fn read_to_end(&mut self) -> io::Result<u64> {
+        let n = io::copy(&mut self.r, &mut io::sink())?;
+        self.eof = true;
+        Ok(self.end() + n)
+    }
+}
+
+/// Polynomial hash of the last [`ANCHOR_SIZE`] bytes.
+struct RollingHash {
+    value: u64,
+    /// `BASE` to the power of `ANCHOR_SIZE - 1`, the weight of the oldest byte.
+    top: u64,
+}
+
+impl RollingHash {
+    const BASE: u64 = 0x100000001b3;
+
+    fn new(bytes: &[u8]) -> Self {
+        let value = bytes.iter().fold(0u64, |h, &b| {
+            h.wrapping_mul(Self::BASE).wrapping_add(b as u64)
+        });
+        let top = (1..bytes.len()).fold(1u64, |p, _| p.wrapping_mul(Self::BASE));
+        Self { value, top }
+    }
+
+    fn roll(&mut self, old: u8, new: u8) {
+        self.value = self
+            .value
+            .wrapping_sub((old as u64).wrapping_mul(self.top))
+            .wrapping_mul(Self::BASE)
+            .wrapping_add(new as u64);
+    }
+
+    fn value(&self) -> u64 {
+        self.value
+    }
+}
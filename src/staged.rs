@@ -0,0 +1,71 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    path::PathBuf,
+};
+
+/// A file written under a temporary name next to its path, then renamed to
+/// it once complete, so that the path may also be that of an input.
+///
+/// The temporary file is removed if dropped before [`Staged::persist`].
+pub struct Staged {
+    path: PathBuf,
+    temp: PathBuf,
+    persisted: bool,
+}
+
+impl Staged {
+    pub fn create(path: &str) -> eyre::Result<(Self, File)> {
+        let path = PathBuf::from(path);
+        let Some(name) = path.file_name() else {
+            eyre::bail!("Cannot create {}: not a file name", path.display());
+        };
+        let mut temp = OsString::from(".");
+        temp.push(name);
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let temp = path.with_file_name(temp);
+
+        let f = File::create(&temp)
+            .map_err(|e| eyre::eyre!("Cannot create {}: {}", temp.display(), e))?;
+        let staged = Self {
+            path,
+            temp,
+            persisted: false,
+        };
+        Ok((staged, f))
+    }
+
+    /// Replace the file at the path by the temporary one.
+    pub fn persist(mut self) -> eyre::Result<()> {
+        fs::rename(&self.temp, &self.path)
+            .map_err(|e| eyre::eyre!("Cannot write {}: {}", self.path.display(), e))?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
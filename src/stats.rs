@@ -0,0 +1,100 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serde::Serialize;
+
+use crate::{Difference, Heatmap, Histogram};
+
+/// Aggregate figures of a comparison.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of bytes compared.
+    pub compared: u64,
+    /// Number of differing words, bytes unless comparing wider words.
+    pub differences: u64,
+    /// Number of differing bits, i.e. the Hamming distance.
+    pub flipped_bits: u64,
+    /// Differing words with exactly one differing bit.
+    pub single_bit_errors: u64,
+    /// Differing words with more than one differing bit.
+    pub multi_bit_errors: u64,
+    /// Bits that are 0 in the first source and 1 in the second one.
+    pub set_bits: u64,
+    /// Bits that are 1 in the first source and 0 in the second one.
+    pub cleared_bits: u64,
+    /// Words which differ only by bits excluded by a [`Mask`](crate::Mask).
+    pub masked_differences: u64,
+    /// Differing bits excluded by a [`Mask`](crate::Mask).
+    pub masked_bits: u64,
+    pub first_offset: Option<u64>,
+    pub last_offset: Option<u64>,
+    /// Flips by bit position.
+    pub histogram: Histogram,
+    /// Flipped bits by block, if requested with [`Differ::heatmap`](crate::Differ::heatmap).
+    #[serde(skip)]
+    pub heatmap: Option<Heatmap>,
+}
+
+impl Stats {
+    pub fn add(&mut self, difference: &Difference) {
+        let xor = difference.xor();
+
+        self.differences += 1;
+        self.flipped_bits += xor.count_ones() as u64;
+        if difference.is_bitflip() {
+            self.single_bit_errors += 1;
+        } else {
+            self.multi_bit_errors += 1;
+        }
+        self.set_bits += (xor & difference.right).count_ones() as u64;
+        self.cleared_bits += (xor & difference.left).count_ones() as u64;
+        self.first_offset.get_or_insert(difference.offset);
+        self.last_offset = Some(difference.offset);
+        self.histogram.add(difference);
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.add(difference);
+        }
+    }
+
+    /// Account the statistics of the comparison following this one.
+    pub fn merge(&mut self, next: &Stats) {
+        self.compared += next.compared;
+        self.differences += next.differences;
+        self.flipped_bits += next.flipped_bits;
+        self.single_bit_errors += next.single_bit_errors;
+        self.multi_bit_errors += next.multi_bit_errors;
+        self.set_bits += next.set_bits;
+        self.cleared_bits += next.cleared_bits;
+        self.masked_differences += next.masked_differences;
+        self.masked_bits += next.masked_bits;
+        self.first_offset = self.first_offset.or(next.first_offset);
+        self.last_offset = next.last_offset.or(self.last_offset);
+        self.histogram.merge(&next.histogram);
+        if let (Some(heatmap), Some(next)) = (&mut self.heatmap, &next.heatmap) {
+            heatmap.merge(next);
+        }
+    }
+
+    /// Ratio of differing bits to compared bits.
+    pub fn bit_error_rate(&self) -> f64 {
+        if self.compared == 0 {
+            return 0.0;
+        }
+        self.flipped_bits as f64 / (self.compared * 8) as f64
+    }
+}
@@ -0,0 +1,72 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serde::Serialize;
+
+use crate::PREVIEW_SIZE;
+
+/// Bytes of the longer source past the end of the shorter one.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Tail {
+    /// The longer source, 1 or 2.
+    pub file: u8,
+    /// Offset of the first byte, counted as those of the differences.
+    pub start: u64,
+    /// Offset following the last byte.
+    pub end: u64,
+    /// 0x00 or 0xff when the tail is nothing else, as padding or erased flash.
+    pub padding: Option<u8>,
+    /// Up to [`PREVIEW_SIZE`] bytes from `start`.
+    #[serde(skip)]
+    pub preview: Vec<u8>,
+}
+
+impl Tail {
+    pub(crate) fn new(file: u8, start: u64) -> Self {
+        Self {
+            file,
+            start,
+            end: start,
+            padding: None,
+            preview: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Account the next bytes of the tail.
+    pub(crate) fn add(&mut self, bytes: &[u8]) {
+        if self.is_empty() {
+            self.padding = bytes.first().copied().filter(|&b| b == 0x00 || b == 0xff);
+        }
+        if let Some(padding) = self.padding {
+            if bytes.iter().any(|&b| b != padding) {
+                self.padding = None;
+            }
+        }
+        let n = std::cmp::min(PREVIEW_SIZE - self.preview.len(), bytes.len());
+        self.preview.extend(&bytes[..n]);
+        self.end += bytes.len() as u64;
+    }
+}
@@ -0,0 +1,390 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::{self, IsTerminal};
+
+use bincmp::{DiffRange, Differ, Endian, MAX_WORD_SIZE};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+/// Shades of the minimap, from no differences to the most of any region.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Interactive view of two files held in memory, as synchronized hex panes.
+pub struct Browser<'a> {
+    names: [&'a str; 2],
+    data: [&'a [u8]; 2],
+    /// Offset of the first byte of each file.
+    skips: [u64; 2],
+    word_size: usize,
+    endian: Endian,
+    /// Differing ranges of words, for the current word size.
+    ranges: Vec<DiffRange>,
+    /// Position of the selected byte.
+    cursor: usize,
+    /// First row shown.
+    top: usize,
+    /// Rows and bytes per row of the panes, as last drawn.
+    rows: usize,
+    row_size: usize,
+    /// Offset being typed, after `g`.
+    input: Option<String>,
+    message: Option<String>,
+}
+
+impl<'a> Browser<'a> {
+    pub fn new(
+        names: [&'a str; 2],
+        data: [&'a [u8]; 2],
+        skips: [u64; 2],
+        word_size: usize,
+        endian: Endian,
+    ) -> eyre::Result<Self> {
+        let mut browser = Self {
+            names,
+            data,
+            skips,
+            word_size,
+            endian,
+            ranges: Vec::new(),
+            cursor: 0,
+            top: 0,
+            rows: 1,
+            row_size: 16,
+            input: None,
+            message: None,
+        };
+        browser.compare()?;
+        Ok(browser)
+    }
+
+    /// Number of differing words, for the current word size.
+    pub fn differences(&self) -> u64 {
+        self.ranges.iter().map(|range| range.differences).sum()
+    }
+
+    /// Browse until the user quits, restoring the terminal either way.
+    pub fn run(mut self) -> eyre::Result<Self> {
+        if !io::stdout().is_terminal() {
+            eyre::bail!("The browser needs a terminal as its standard output");
+        }
+        let mut terminal = match ratatui::try_init() {
+            Ok(terminal) => terminal,
+            Err(e) => {
+                ratatui::restore();
+                eyre::bail!("Cannot open the terminal: {}", e);
+            }
+        };
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result.map(|()| self)
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> eyre::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.key(key)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Find the differing ranges, with the library comparison of the current word size.
+    fn compare(&mut self) -> eyre::Result<()> {
+        let [left, right] = self.data;
+        let ranges = Differ::new(left, right)
+            .word_size(self.word_size)
+            .endian(self.endian)
+            .parallel()
+            .ranges(0);
+        self.ranges = ranges.collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        std::cmp::max(self.data[0].len(), self.data[1].len())
+    }
+
+    /// Handle a key, returning false to quit.
+    fn key(&mut self, key: KeyEvent) -> eyre::Result<bool> {
+        self.message = None;
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Char(c) if c.is_ascii_hexdigit() || c == 'x' => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => {
+                    let input = self.input.take().unwrap_or_default();
+                    self.goto(&input);
+                }
+                KeyCode::Esc => self.input = None,
+                _ => (),
+            }
+            return Ok(true);
+        }
+
+        let (row, page) = (self.row_size, self.row_size * self.rows);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(row),
+            KeyCode::Down => self.cursor += row,
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(self.word_size),
+            KeyCode::Right => self.cursor += self.word_size,
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            KeyCode::PageDown => self.cursor += page,
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.len(),
+            KeyCode::Char('n') => self.next_difference(),
+            KeyCode::Char('p') => self.previous_difference(),
+            KeyCode::Char('g') => self.input = Some(String::new()),
+            KeyCode::Char('w') => {
+                self.word_size = match self.word_size {
+                    MAX_WORD_SIZE => 1,
+                    size => size * 2,
+                };
+                self.compare()?;
+            }
+            KeyCode::Char('e') => {
+                self.endian = match self.endian {
+                    Endian::Little => Endian::Big,
+                    Endian::Big => Endian::Little,
+                };
+            }
+            _ => (),
+        }
+        self.cursor = self.cursor.min(self.len().saturating_sub(1));
+        self.cursor -= self.cursor % self.word_size;
+        Ok(true)
+    }
+
+    /// Move to an offset of the first file, in hex.
+    fn goto(&mut self, input: &str) {
+        let hex = input.strip_prefix("0x").unwrap_or(input);
+        match u64::from_str_radix(hex, 16) {
+            Ok(offset) if offset >= self.skips[0] => {
+                let pos = (offset - self.skips[0]).min(self.len().saturating_sub(1) as u64);
+                self.cursor = pos as usize - pos as usize % self.word_size;
+            }
+            _ => self.message = Some(format!("Invalid offset {:?}", input)),
+        }
+    }
+
+    fn next_difference(&mut self) {
+        let cursor = self.cursor as u64;
+        let i = self.ranges.partition_point(|range| range.start <= cursor);
+        match self.ranges.get(i) {
+            Some(range) => self.cursor = range.start as usize,
+            None => self.message = Some("No further difference".to_string()),
+        }
+    }
+
+    fn previous_difference(&mut self) {
+        let cursor = self.cursor as u64;
+        let i = self.ranges.partition_point(|range| range.start < cursor);
+        match i.checked_sub(1).map(|i| &self.ranges[i]) {
+            Some(range) => self.cursor = range.start as usize,
+            None => self.message = Some("No previous difference".to_string()),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [left, right, minimap] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(3),
+        ])
+        .areas(main);
+
+        // As many bytes per row as fit, in words.
+        let fit = (left.width.saturating_sub(12) / 4) as usize;
+        self.row_size = match fit {
+            16.. => 16,
+            8.. => 8,
+            _ => 4,
+        }
+        .max(self.word_size);
+        self.rows = main.height.saturating_sub(2).max(1) as usize;
+
+        let cursor_row = self.cursor / self.row_size;
+        if cursor_row < self.top {
+            self.top = cursor_row;
+        } else if cursor_row >= self.top + self.rows {
+            self.top = cursor_row + 1 - self.rows;
+        }
+
+        frame.render_widget(self.pane(0), left);
+        frame.render_widget(self.pane(1), right);
+        frame.render_widget(self.minimap(minimap), minimap);
+        frame.render_widget(Paragraph::new(self.status()), status);
+        frame.render_widget(
+            Paragraph::new(
+                "q quit  ↑↓←→ PgUp PgDn Home End move  n/p next/previous difference  \
+                 g go to offset  w word size  e endianness",
+            )
+            .style(Style::new().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    /// Whether the word at `pos` differs between both files.
+    fn differs(&self, pos: usize) -> bool {
+        let start = pos - pos % self.word_size;
+        let end = start + self.word_size;
+        let [left, right] = self.data;
+        left.get(start..end.min(left.len())) != right.get(start..end.min(right.len()))
+    }
+
+    fn pane(&self, side: usize) -> Paragraph<'_> {
+        let data = self.data[side];
+        let diff = Style::new().fg(Color::Red).add_modifier(Modifier::BOLD);
+        let cursor = Style::new().add_modifier(Modifier::REVERSED);
+
+        let lines = (self.top..self.top + self.rows).map(|row| {
+            let start = row * self.row_size;
+            if start >= data.len() {
+                return Line::default();
+            }
+            let bytes = &data[start..(start + self.row_size).min(data.len())];
+            let offset = self.skips[side] + start as u64;
+
+            let mut spans = vec![Span::raw(format!("{:08x} ", offset))];
+            for (i, &byte) in bytes.iter().enumerate() {
+                let pos = start + i;
+                if pos.is_multiple_of(self.word_size) {
+                    spans.push(Span::raw(" "));
+                }
+                let mut style = Style::new();
+                if self.differs(pos) {
+                    style = style.patch(diff);
+                }
+                if pos == self.cursor {
+                    style = style.patch(cursor);
+                }
+                spans.push(Span::styled(format!("{:02x}", byte), style));
+            }
+
+            let padding = self.row_size - bytes.len();
+            spans.push(Span::raw(
+                " ".repeat(padding * 2 + padding / self.word_size + 2),
+            ));
+            for (i, &byte) in bytes.iter().enumerate() {
+                let c = match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                };
+                let style = match self.differs(start + i) {
+                    true => diff,
+                    false => Style::new(),
+                };
+                spans.push(Span::styled(c.to_string(), style));
+            }
+            Line::from(spans)
+        });
+
+        let block = Block::bordered().title(self.names[side]);
+        Paragraph::new(lines.collect::<Vec<_>>()).block(block)
+    }
+
+    /// Where the differences lie, a cell per region of the files, with the
+    /// part shown in the panes highlighted.
+    fn minimap(&self, area: Rect) -> Paragraph<'_> {
+        let height = area.height.saturating_sub(2).max(1) as usize;
+        let len = self.len().max(1) as u64;
+        let region = |offset: u64| (offset * height as u64 / len) as usize;
+
+        let mut counts = vec![0u64; height];
+        for range in &self.ranges {
+            counts[region(range.start).min(height - 1)] += range.differences;
+        }
+        let max = counts.iter().copied().max().unwrap_or_default().max(1);
+
+        let first = region((self.top * self.row_size) as u64);
+        let last = region(((self.top + self.rows) * self.row_size) as u64);
+        let lines: Vec<Line> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| {
+                let shade = match count {
+                    0 => 0,
+                    _ => (1 + count * (SHADES.len() as u64 - 2) / max) as usize,
+                };
+                let style = match (first..=last).contains(&i) {
+                    true => Style::new().bg(Color::DarkGray),
+                    false => Style::new(),
+                };
+                Line::styled(SHADES[shade].to_string(), style.fg(Color::Red))
+            })
+            .collect();
+
+        Paragraph::new(lines).block(Block::bordered())
+    }
+
+    fn status(&self) -> Line<'_> {
+        if let Some(input) = &self.input {
+            return Line::from(format!("Go to offset: {}", input));
+        }
+
+        let start = self.cursor;
+        let word = |data: &[u8]| match data.get(start..start + self.word_size) {
+            Some(bytes) => format!(
+                "{:0width$x}",
+                self.endian.decode(bytes),
+                width = 2 * bytes.len()
+            ),
+            None => "-".to_string(),
+        };
+        let cursor = start as u64;
+        let range = self.ranges.partition_point(|range| range.start <= cursor);
+        let endian = match self.endian {
+            Endian::Little => "little",
+            Endian::Big => "big",
+        };
+
+        let mut status = format!(
+            "{:08x}  {}-bit {}  {} {}  range {}/{}, {} differing words",
+            self.skips[0] + cursor,
+            self.word_size * 8,
+            endian,
+            word(self.data[0]),
+            word(self.data[1]),
+            range,
+            self.ranges.len(),
+            self.differences(),
+        );
+        if let Some(message) = &self.message {
+            status += "  ";
+            status += message;
+        }
+        Line::from(status)
+    }
+}
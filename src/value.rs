@@ -0,0 +1,165 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Numeric interpretation of the compared words.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ValueType {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValueType {
+    /// Width of the type in bits.
+    pub fn bits(self) -> usize {
+        match self {
+            ValueType::I8 => 8,
+            ValueType::I16 => 16,
+            ValueType::I32 | ValueType::F32 => 32,
+            ValueType::I64 | ValueType::F64 => 64,
+        }
+    }
+
+    pub fn decode(self, raw: u64) -> Value {
+        match self {
+            ValueType::I8 => Value::Int(raw as u8 as i8 as i128),
+            ValueType::I16 => Value::Int(raw as u16 as i16 as i128),
+            ValueType::I32 => Value::Int(raw as u32 as i32 as i128),
+            ValueType::I64 => Value::Int(raw as i64 as i128),
+            ValueType::F32 => Value::F32(f32::from_bits(raw as u32)),
+            ValueType::F64 => Value::F64(f64::from_bits(raw)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i128),
+    F32(f32),
+    F64(f64),
+}
+
+impl Value {
+    /// `right - left`, both being of the same type.
+    pub fn delta(left: Value, right: Value) -> Value {
+        match (left, right) {
+            (Value::Int(left), Value::Int(right)) => Value::Int(right - left),
+            (Value::F32(left), Value::F32(right)) => Value::F32(right - left),
+            (Value::F64(left), Value::F64(right)) => Value::F64(right - left),
+            _ => unreachable!("values of different types"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::F32(v) => write_float(f, *v),
+            Value::F64(v) => write_float(f, *v),
+        }
+    }
+}
+
+/// Write `v`, in scientific notation for very small or large magnitudes.
+fn write_float<T>(f: &mut fmt::Formatter<'_>, v: T) -> fmt::Result
+where
+    T: Copy + Into<f64> + fmt::Display + fmt::LowerExp,
+{
+    let magnitude = v.into().abs();
+    if magnitude != 0.0 && !(1e-4..1e16).contains(&magnitude) {
+        write!(f, "{:e}", v)
+    } else {
+        write!(f, "{}", v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincmp::Endian;
+
+    use super::*;
+
+    #[test]
+    fn integers() {
+        assert_eq!(ValueType::I8.decode(0x7f), Value::Int(127));
+        assert_eq!(ValueType::I8.decode(0x80), Value::Int(-128));
+        assert_eq!(ValueType::I16.decode(0xfffe), Value::Int(-2));
+        assert_eq!(ValueType::I32.decode(0x8000_0000), Value::Int(-(1 << 31)));
+        assert_eq!(ValueType::I64.decode(u64::MAX), Value::Int(-1));
+        assert_eq!(
+            ValueType::I64.decode(i64::MAX as u64),
+            Value::Int(i64::MAX as i128)
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(ValueType::F32.decode(0x3fc0_0000), Value::F32(1.5));
+        assert_eq!(
+            ValueType::F64.decode(0xc004_0000_0000_0000),
+            Value::F64(-2.5)
+        );
+    }
+
+    #[test]
+    fn byte_order() {
+        let bytes = [0xff, 0xfe];
+        assert_eq!(
+            ValueType::I16.decode(Endian::Little.decode(&bytes)),
+            Value::Int(-257)
+        );
+        assert_eq!(
+            ValueType::I16.decode(Endian::Big.decode(&bytes)),
+            Value::Int(-2)
+        );
+    }
+
+    #[test]
+    fn delta() {
+        // Integers of the widest type do not overflow.
+        let delta = Value::delta(
+            ValueType::I64.decode(i64::MIN as u64),
+            ValueType::I64.decode(i64::MAX as u64),
+        );
+        assert_eq!(delta, Value::Int(u64::MAX as i128));
+        assert_eq!(
+            Value::delta(Value::F32(1.5), Value::F32(-0.5)),
+            Value::F32(-2.0)
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Int(-3).to_string(), "-3");
+        assert_eq!(Value::F32(1.5).to_string(), "1.5");
+        assert_eq!(Value::F64(0.0).to_string(), "0");
+        assert_eq!(Value::F64(1e-5).to_string(), "1e-5");
+        assert_eq!(Value::F32(-3e20).to_string(), "-3e20");
+        assert_eq!(Value::F64(f64::NAN).to_string(), "NaN");
+    }
+}
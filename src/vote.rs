@@ -0,0 +1,269 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Sink, Write},
+};
+
+use serde::Serialize;
+
+use crate::{differ::read_full, Difference, Stats, BUFFER_SIZE};
+
+/// A byte on which the sources do not all agree.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct UnstableByte {
+    pub offset: u64,
+    /// Reconstructed value, the majority of each bit.
+    pub vote: u8,
+    /// Value in each source.
+    pub values: Vec<u8>,
+    /// Bits on which the sources disagree.
+    pub bits: u8,
+    /// Number of sources agreeing with the vote on its least certain bit.
+    pub agreement: usize,
+}
+
+impl UnstableByte {
+    /// Share of the sources agreeing with the vote on its least certain bit.
+    pub fn confidence(&self) -> f64 {
+        self.agreement as f64 / self.values.len() as f64
+    }
+
+    /// Whether some bit has no majority, and is kept as in the first source.
+    pub fn is_tie(&self) -> bool {
+        self.agreement * 2 == self.values.len()
+    }
+}
+
+/// Aggregate figures of a vote.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct VoteStats {
+    /// Number of bytes voted on.
+    pub voted: u64,
+    pub unstable_bytes: u64,
+    pub unstable_bits: u64,
+    /// Unstable bits without a majority.
+    pub tied_bits: u64,
+    /// Confidence of the least certain bit, 1 when all sources agree.
+    pub confidence: f64,
+    pub first_offset: Option<u64>,
+    pub last_offset: Option<u64>,
+}
+
+impl VoteStats {
+    pub fn add(&mut self, unstable: &UnstableByte) {
+        self.unstable_bytes += 1;
+        self.unstable_bits += unstable.bits.count_ones() as u64;
+        self.confidence = self.confidence.min(unstable.confidence());
+        self.first_offset.get_or_insert(unstable.offset);
+        self.last_offset = Some(unstable.offset);
+    }
+}
+
+/// Bitwise majority vote over several reads of the same data.
+///
+/// Each bit of the reconstructed image takes the value found in most of the
+/// sources, or that of the first source on a tie, and the bytes on which the
+/// sources disagree are yielded as [`UnstableByte`]s. The sources are read in
+/// chunks of [`BUFFER_SIZE`] bytes until the shorter one ends, and the image
+/// is written to the [`Vote::image`] writer as it is reconstructed.
+pub struct Vote<R, W = Sink> {
+    sources: Vec<R>,
+    image: W,
+    buffers: Vec<[u8; BUFFER_SIZE]>,
+    /// Offset of the current chunk.
+    offset: u64,
+    /// Whether all sources ended together, once one of them did.
+    eof: Option<bool>,
+    pending: VecDeque<UnstableByte>,
+    stats: VoteStats,
+    /// Deviations of each source from the vote, as differences from it,
+    /// telling single bit flips from other errors.
+    deviations: Vec<Stats>,
+}
+
+impl<R: Read> Vote<R> {
+    /// # Panics
+    ///
+    /// If there are less than 3 sources, too few for a majority.
+    pub fn new(sources: Vec<R>) -> Self {
+        assert!(sources.len() >= 3, "a vote needs at least 3 sources");
+        let n = sources.len();
+        Self {
+            sources,
+            image: io::sink(),
+            buffers: vec![[0u8; BUFFER_SIZE]; n],
+            offset: 0,
+            eof: None,
+            pending: VecDeque::new(),
+            stats: VoteStats {
+                confidence: 1.0,
+                ..VoteStats::default()
+            },
+            deviations: vec![Stats::default(); n],
+        }
+    }
+}
+
+impl<R: Read, W: Write> Vote<R, W> {
+    /// Offset reported for the first byte, for sources which do not start at
+    /// the beginning of their file.
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Write the reconstructed image to `image`.
+    pub fn image<W2: Write>(self, image: W2) -> Vote<R, W2> {
+        Vote {
+            sources: self.sources,
+            image,
+            buffers: self.buffers,
+            offset: self.offset,
+            eof: self.eof,
+            pending: self.pending,
+            stats: self.stats,
+            deviations: self.deviations,
+        }
+    }
+
+    /// Statistics of the vote, complete once it is done.
+    pub fn stats(&self) -> VoteStats {
+        self.stats.clone()
+    }
+
+    /// Statistics of each source compared against the reconstructed image.
+    pub fn deviations(&self) -> Vec<Stats> {
+        self.deviations.clone()
+    }
+
+    /// Whether all sources have the same length, once the vote is done.
+    pub fn same_length(&self) -> Option<bool> {
+        self.eof
+    }
+
+    /// Read and vote on the next chunk.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut lengths = Vec::with_capacity(self.sources.len());
+        for (source, buffer) in self.sources.iter_mut().zip(&mut self.buffers) {
+            lengths.push(read_full(source, buffer)?);
+        }
+        let len = lengths.iter().copied().min().unwrap_or_default();
+        if len < BUFFER_SIZE {
+            self.eof = Some(lengths.iter().all(|&n| n == len));
+        }
+
+        let mut image = self.buffers[0];
+        let stable = self.buffers[1..]
+            .iter()
+            .all(|buffer| buffer[..len] == image[..len]);
+        if !stable {
+            for pos in 0..len {
+                let values: Vec<u8> = self.buffers.iter().map(|buffer| buffer[pos]).collect();
+                if values.iter().all(|&value| value == values[0]) {
+                    continue;
+                }
+                let unstable = vote(self.offset + pos as u64, values);
+                image[pos] = unstable.vote;
+                self.account(&unstable);
+                self.pending.push_back(unstable);
+            }
+        }
+        self.image.write_all(&image[..len])?;
+
+        self.stats.voted += len as u64;
+        for stats in &mut self.deviations {
+            stats.compared += len as u64;
+        }
+        self.offset += len as u64;
+
+        if self.eof.is_some() {
+            self.image.flush()?;
+        }
+        Ok(())
+    }
+
+    fn account(&mut self, unstable: &UnstableByte) {
+        self.stats.add(unstable);
+        if unstable.is_tie() {
+            let values = &unstable.values;
+            let tied = (0..u8::BITS).filter(|bit| {
+                values.iter().filter(|&&v| v & (1 << bit) != 0).count() * 2 == values.len()
+            });
+            self.stats.tied_bits += tied.count() as u64;
+        }
+        for (stats, &value) in self.deviations.iter_mut().zip(&unstable.values) {
+            if value != unstable.vote {
+                stats.add(&Difference {
+                    offset: unstable.offset,
+                    size: 1,
+                    left: unstable.vote as u64,
+                    right: value as u64,
+                    ignored: 0,
+                });
+            }
+        }
+    }
+
+    fn next_unstable(&mut self) -> io::Result<Option<UnstableByte>> {
+        while self.pending.is_empty() && self.eof.is_none() {
+            self.fill()?;
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+/// Majority of each bit of `values`, which do not all agree.
+fn vote(offset: u64, values: Vec<u8>) -> UnstableByte {
+    let n = values.len();
+    let (mut vote, mut bits, mut agreement) = (0u8, 0u8, n);
+
+    for bit in 0..u8::BITS {
+        let mask = 1 << bit;
+        let ones = values.iter().filter(|&&v| v & mask != 0).count();
+        let set = match (ones * 2).cmp(&n) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => values[0] & mask != 0,
+        };
+        if set {
+            vote |= mask;
+        }
+        if ones != 0 && ones != n {
+            bits |= mask;
+            agreement = agreement.min(std::cmp::max(ones, n - ones));
+        }
+    }
+
+    UnstableByte {
+        offset,
+        vote,
+        values,
+        bits,
+        agreement,
+    }
+}
+
+impl<R: Read, W: Write> Iterator for Vote<R, W> {
+    type Item = io::Result<UnstableByte>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_unstable().transpose()
+    }
+}
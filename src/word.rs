@@ -0,0 +1,44 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// Byte order of the words being compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Value of a word of up to 8 bytes.
+    pub fn decode(self, bytes: &[u8]) -> u64 {
+        let fold = |value: u64, b: &u8| value << 8 | *b as u64;
+        match self {
+            Endian::Little => bytes.iter().rev().fold(0, fold),
+            Endian::Big => bytes.iter().fold(0, fold),
+        }
+    }
+
+    /// Offset within a word of `size` bytes of its byte of the given significance.
+    pub(crate) fn byte_offset(self, significance: usize, size: usize) -> usize {
+        match self {
+            Endian::Little => significance,
+            Endian::Big => size - 1 - significance,
+        }
+    }
+}
@@ -0,0 +1,499 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Reports of the command line tool, for modes reading every file from the
+//! same offset.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+/// Write `files` into a directory of their own, returning its path.
+fn fixture(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, data) in files {
+        std::fs::write(dir.join(file), data).unwrap();
+    }
+    dir
+}
+
+fn bincmp(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bincmp"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn vote_skip_csv() {
+    let good = b"0123456789";
+    let dir = fixture(
+        "vote_skip_csv",
+        &[("s1", good), ("s2", b"0123x56789"), ("s3", good)],
+    );
+
+    let output = bincmp(
+        &dir,
+        &[
+            "--vote", "out.img", "--skip1", "1", "-o", "csv", "s1", "s2", "s3", "s1",
+        ],
+    );
+
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "offset,vote,file1,file2,file3,file4,bits,confidence\n\
+         4,52,52,120,52,52,76,0.75\n"
+    );
+    assert_eq!(std::fs::read(dir.join("out.img")).unwrap(), &good[1..]);
+}
+
+#[test]
+fn three_way_skip_csv() {
+    let dir = fixture(
+        "three_way_skip_csv",
+        &[
+            ("base", b"0123456789"),
+            ("ours", b"0123x56789"),
+            ("theirs", b"01234567y9"),
+        ],
+    );
+
+    let output = bincmp(
+        &dir,
+        &[
+            "--three-way",
+            "--skip1",
+            "1",
+            "-o",
+            "csv",
+            "base",
+            "ours",
+            "theirs",
+        ],
+    );
+
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "kind,start,end,length,base_preview,ours_preview,theirs_preview\n\
+         ours,4,5,1,34,78,34\n\
+         theirs,8,9,1,38,38,79\n"
+    );
+}
+
+#[test]
+fn pad_hex() {
+    let dir = fixture(
+        "pad_hex",
+        &[("long", b"\x00\x01\xff\xff"), ("short", b"\x00\x01")],
+    );
+
+    for pad in ["ff", "0xff", "0XFF"] {
+        let output = bincmp(&dir, &["-q", "--pad", pad, "long", "short"]);
+        assert_eq!(output.status.code(), Some(0), "{}: {:?}", pad, output);
+        let output = bincmp(&dir, &["-q", "--against-pattern", pad, "long"]);
+        assert_eq!(output.status.code(), Some(1), "{}: {:?}", pad, output);
+    }
+    let output = bincmp(&dir, &["-q", "--pad", "00", "long", "short"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    for pad in ["f", "00ff", "xy"] {
+        let output = bincmp(&dir, &["-q", "--pad", pad, "long", "short"]);
+        assert_eq!(output.status.code(), Some(2), "{}: {:?}", pad, output);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn several_files_from_pipe() {
+    let dir = fixture("several_files_from_pipe", &[("a", b"0123"), ("b", b"0123")]);
+
+    // Standard input is a pipe, which could be read only once.
+    for file1 in ["-", "/dev/stdin"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_bincmp"))
+            .current_dir(&dir)
+            .args([file1, "a", "b"])
+            .stdin(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{}: {:?}", file1, output);
+    }
+
+    let output = bincmp(&dir, &["a", "b", "a"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+}
+
+#[test]
+fn several_files_bit_error_rate() {
+    let dir = fixture(
+        "several_files_bit_error_rate",
+        &[("a", b"abcd"), ("b", b"abXd"), ("c", b"abcd")],
+    );
+
+    let output = bincmp(&dir, &["a", "b", "c", "--stats"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // Rounded like the bit error rate of two files.
+    assert!(stdout.contains(" 1.562e-1 "), "{}", stdout);
+    assert!(stdout.contains(" 0.000e0 "), "{}", stdout);
+
+    let output = bincmp(&dir, &["a", "b", "--stats"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("bit error rate      1.562e-1\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn vote_majority() {
+    let dir = fixture(
+        "vote_majority",
+        &[
+            ("s1", b"\x00\xf0"),
+            ("s2", b"\x01\xf0"),
+            ("s3", b"\x00\x70"),
+        ],
+    );
+
+    let output = bincmp(&dir, &["--vote", "out.img", "s1", "s2", "s3"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("out.img")).unwrap(), b"\x00\xf0");
+
+    let output = bincmp(&dir, &["--vote", "out.img", "s1", "s1", "s1"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+}
+
+#[test]
+fn vote_tie() {
+    let dir = fixture(
+        "vote_tie",
+        &[
+            ("s1", b"\x00"),
+            ("s2", b"\x00"),
+            ("s3", b"\x03"),
+            ("s4", b"\x03"),
+        ],
+    );
+
+    // On a tie, each bit takes the value of the first file.
+    let output = bincmp(
+        &dir,
+        &["--vote", "out.img", "-o", "csv", "s1", "s3", "s2", "s4"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "offset,vote,file1,file2,file3,file4,bits,confidence\n\
+         0,0,0,3,0,3,3,0.5\n"
+    );
+    assert_eq!(std::fs::read(dir.join("out.img")).unwrap(), b"\x00");
+
+    let output = bincmp(&dir, &["--vote", "out.img", "s3", "s1", "s2", "s4"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("out.img")).unwrap(), b"\x03");
+}
+
+#[test]
+fn vote_over_input() {
+    let dir = fixture(
+        "vote_over_input",
+        &[
+            ("s1", b"0123x56789"),
+            ("s2", b"0123456789"),
+            ("s3", b"0123456789"),
+        ],
+    );
+
+    let output = bincmp(&dir, &["--vote", "s1", "s1", "s2", "s3"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("s1")).unwrap(), b"0123456789");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+}
+
+#[test]
+fn merge_clean() {
+    let dir = fixture(
+        "merge_clean",
+        &[
+            ("base", b"0123456789"),
+            ("ours", b"0123x56789"),
+            ("theirs", b"01234567y9"),
+        ],
+    );
+
+    let output = bincmp(
+        &dir,
+        &["--three-way", "base", "ours", "theirs", "--merged", "out"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("out")).unwrap(), b"0123x567y9");
+}
+
+#[test]
+fn merge_conflict() {
+    let dir = fixture(
+        "merge_conflict",
+        &[
+            ("base", b"0123456789"),
+            ("ours", b"0123x56789"),
+            ("theirs", b"0123y567y9"),
+            ("out", b"previous"),
+        ],
+    );
+
+    let output = bincmp(
+        &dir,
+        &[
+            "--three-way",
+            "-o",
+            "csv",
+            "base",
+            "ours",
+            "theirs",
+            "--merged",
+            "out",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "kind,start,end,length,base_preview,ours_preview,theirs_preview\n\
+         conflict,4,5,1,34,78,79\n\
+         theirs,8,9,1,38,38,79\n"
+    );
+    assert_eq!(std::fs::read(dir.join("out")).unwrap(), b"previous");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+}
+
+#[test]
+fn merge_over_input() {
+    let base = b"0123456789";
+    let dir = fixture(
+        "merge_over_input",
+        &[
+            ("base", base),
+            ("ours", b"0123x56789"),
+            ("theirs", b"01234567y9"),
+            ("other", b"0123y56789"),
+        ],
+    );
+
+    let output = bincmp(
+        &dir,
+        &["--three-way", "base", "ours", "theirs", "--merged", "ours"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("ours")).unwrap(), b"0123x567y9");
+
+    // A conflicting merge leaves the files as they were.
+    let output = bincmp(
+        &dir,
+        &["--three-way", "base", "ours", "other", "--merged", "base"],
+    );
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("base")).unwrap(), base);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+}
+
+#[test]
+fn merge_append() {
+    let dir = fixture(
+        "merge_append",
+        &[
+            ("base", b"0123"),
+            ("ours", b"0123abc"),
+            ("theirs", b"x123"),
+            ("short", b"012"),
+        ],
+    );
+
+    let output = bincmp(
+        &dir,
+        &["--three-way", "base", "ours", "theirs", "--merged", "out"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(std::fs::read(dir.join("out")).unwrap(), b"x123abc");
+
+    let output = bincmp(&dir, &["-o", "csv", "--three-way", "base", "ours", "short"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "kind,start,end,length,base_preview,ours_preview,theirs_preview\n\
+         conflict,3,4,1,33,33,\n\
+         ours,4,7,3,,616263,\n"
+    );
+}
+
+#[test]
+fn tui_without_terminal() {
+    let dir = fixture("tui_without_terminal", &[("a", b"0123"), ("b", b"0x23")]);
+
+    let output = bincmp(&dir, &["--tui", "a", "b"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+}
+
+#[test]
+fn hexdump_no_context() {
+    let left = [b'a'; 64];
+    let mut right = left;
+    right[0x05] = b'b';
+    right[0x35] = b'b';
+    let dir = fixture("hexdump_no_context", &[("a", &left), ("b", &right)]);
+
+    let output = bincmp(&dir, &["--hexdump", "-U", "0", "a", "b"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<_> = stdout
+        .lines()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(rows, ["00000000", "--", "00000030"]);
+
+    let output = bincmp(&dir, &["--hexdump", "a", "b"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 4, "{}", stdout);
+}
+
+#[test]
+fn html_escaping() {
+    let dir = fixture(
+        "html_escaping",
+        &[("a<b&c", b"0123<&\"x"), ("d", b"0123>'\"y")],
+    );
+
+    let output = bincmp(&dir, &["-o", "html", "a<b&c", "d"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let html = String::from_utf8(output.stdout).unwrap();
+    assert!(html.contains("<h1>a&lt;b&amp;c vs d</h1>"), "{}", html);
+    assert!(!html.contains("a<b"), "{}", html);
+    // The ASCII of the first file, its differing characters marked.
+    assert!(
+        html.contains(
+            " 0123<span class=\"d\">&lt;</span><span class=\"d\">&amp;</span>&quot;\
+             <span class=\"d\">x</span> "
+        ),
+        "{}",
+        html
+    );
+    assert!(html.contains("<span class=\"d\">&gt;</span>"), "{}", html);
+}
+
+#[test]
+fn html_cut_off() {
+    let dir = fixture(
+        "html_cut_off",
+        &[("a", &[0x00; 100 * 16]), ("b", &[0xff; 100 * 16])],
+    );
+
+    let output = bincmp(&dir, &["-o", "html", "a", "b"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let html = String::from_utf8(output.stdout).unwrap();
+    let rows = html
+        .lines()
+        .filter(|line| line.starts_with("000") && line.contains("   000"))
+        .count();
+    assert_eq!(rows, 64, "{}", html);
+    assert!(html.contains("\n... 36 more rows\n"), "{}", html);
+}
+
+/// Files of 7 blocks of 16 bytes, the last one partial, differing by a bit in
+/// the first block and by a byte in the fourth one.
+fn heatmap_fixture(name: &str) -> PathBuf {
+    let left = [0x55; 100];
+    let mut right = left;
+    right[0] ^= 0x01;
+    right[50] ^= 0xff;
+    fixture(name, &[("a", &left), ("b", &right)])
+}
+
+/// RGB of the heatmap pixels of `heatmap_fixture`, in rows of 4 pixels.
+const HEATMAP_PIXELS: [[u8; 3]; 8] = [
+    [0x80, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0xff, 0xff, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0x40, 0x40, 0x40],
+];
+
+#[test]
+fn heatmap_ppm() {
+    let dir = heatmap_fixture("heatmap_ppm");
+
+    let args = [
+        "--heatmap",
+        "out.ppm",
+        "--heatmap-width",
+        "4",
+        "--heatmap-block",
+        "16",
+        "a",
+        "b",
+    ];
+    let mut expected = b"P6\n4 2\n255\n".to_vec();
+    expected.extend(HEATMAP_PIXELS.concat());
+    // Drawn the same whether compared in parallel or sequentially.
+    for mmap in [&[][..], &["--no-mmap"]] {
+        let output = bincmp(&dir, &[&args[..], mmap].concat());
+        assert_eq!(output.status.code(), Some(1), "{:?}", output);
+        assert_eq!(std::fs::read(dir.join("out.ppm")).unwrap(), expected);
+        std::fs::remove_file(dir.join("out.ppm")).unwrap();
+    }
+}
+
+#[test]
+fn heatmap_png() {
+    let dir = heatmap_fixture("heatmap_png");
+
+    let args = [
+        "--heatmap",
+        "out.PNG",
+        "--heatmap-width",
+        "4",
+        "--heatmap-block",
+        "16",
+        "a",
+        "b",
+    ];
+    let output = bincmp(&dir, &args);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+
+    let decoder = png::Decoder::new(std::fs::File::open(dir.join("out.PNG")).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (4, 2));
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    assert_eq!(pixels[..info.buffer_size()], HEATMAP_PIXELS.concat());
+}
+
+#[test]
+fn heatmap_unknown_format() {
+    let dir = heatmap_fixture("heatmap_unknown_format");
+
+    let output = bincmp(&dir, &["--heatmap", "out.gif", "a", "b"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(!dir.join("out.gif").exists());
+}
@@ -0,0 +1,46 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Fixtures shared by the integration tests.
+
+// Every test crate includes this module but uses only part of it.
+#![allow(dead_code)]
+
+/// Pseudo-random data, so that differences and anchors occur only where
+/// intended.
+pub fn data(len: usize) -> Vec<u8> {
+    let mut x = 0x2545f491u32;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+/// Pseudo-random data, with differences every `step` bytes in the second source.
+pub fn sources(len: usize, step: usize) -> (Vec<u8>, Vec<u8>) {
+    let left = data(len);
+    let mut right = left.clone();
+    for (i, v) in right.iter_mut().enumerate().step_by(step) {
+        *v ^= 1 << (i % 8);
+    }
+    (left, right)
+}
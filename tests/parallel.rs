@@ -0,0 +1,138 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Sources compared in parallel, chunk by chunk, must compare the same as
+//! sources compared sequentially.
+
+use std::io;
+
+use bincmp::{Differ, Mask, CHUNK_SIZE};
+
+mod common;
+
+type SliceDiffer<'a> = Differ<&'a [u8], &'a [u8]>;
+
+/// Pseudo-random data, with differences every `step` bytes in the second
+/// source, and around each chunk boundary.
+fn sources(len: usize, step: usize) -> (Vec<u8>, Vec<u8>) {
+    let (left, mut right) = common::sources(len, step);
+    // Alternately differences a few bytes apart on either side of the
+    // boundary, and a run straddling it.
+    for (i, boundary) in (CHUNK_SIZE..len).step_by(CHUNK_SIZE).enumerate() {
+        if i % 2 == 0 {
+            right[boundary - 2] ^= 0x01;
+            right[boundary + 1] ^= 0x80;
+        } else {
+            for v in &mut right[boundary - 5..boundary + 5] {
+                *v = !*v;
+            }
+        }
+    }
+    (left, right)
+}
+
+/// Compare the differences, ranges, statistics and tails of both paths.
+fn assert_same<'a>(
+    left: &'a [u8],
+    right: &'a [u8],
+    gap: u64,
+    settings: fn(SliceDiffer<'a>) -> SliceDiffer<'a>,
+) {
+    let mut sequential = settings(Differ::new(left, right));
+    let expected: Vec<_> = (&mut sequential).collect::<io::Result<_>>().unwrap();
+    let mut parallel = settings(Differ::new(left, right)).parallel();
+    let found: Vec<_> = (&mut parallel).collect::<io::Result<_>>().unwrap();
+
+    assert_eq!(found, expected);
+    assert_eq!(parallel.stats(), sequential.stats());
+    assert_eq!(parallel.eof_ordering(), sequential.eof_ordering());
+    assert_eq!(parallel.tail(), sequential.tail().unwrap());
+
+    let mut sequential = settings(Differ::new(left, right)).ranges(gap);
+    let expected: Vec<_> = (&mut sequential).collect::<io::Result<_>>().unwrap();
+    let mut parallel = settings(Differ::new(left, right)).parallel().ranges(gap);
+    let found: Vec<_> = (&mut parallel).collect::<io::Result<_>>().unwrap();
+
+    assert_eq!(found, expected);
+    assert_eq!(parallel.stats(), sequential.stats());
+    assert_eq!(parallel.eof_ordering(), sequential.eof_ordering());
+    assert_eq!(parallel.tail(), sequential.tail().unwrap());
+}
+
+#[test]
+fn differences() {
+    let (left, right) = sources(3 * CHUNK_SIZE + 1234, 4099);
+
+    assert_same(&left, &right, 0, |differ| differ);
+    assert_same(&left, &right, 4, |differ| differ.start_offset(0x100));
+    assert_same(&left, &right, 4, |differ| {
+        differ.heatmap(4096).start_offset(0x100)
+    });
+}
+
+#[test]
+fn ranges_across_chunks() {
+    let (left, right) = sources(3 * CHUNK_SIZE + 1234, 4099);
+
+    // A gap larger than the distance between the differences around the
+    // first boundary merges them into a single range.
+    let mut ranges = Differ::new(&left[..], &right[..]).parallel().ranges(4);
+    let found: Vec<_> = (&mut ranges).collect::<io::Result<_>>().unwrap();
+    let straddling = found
+        .iter()
+        .filter(|range| range.start < CHUNK_SIZE as u64 && range.end > CHUNK_SIZE as u64)
+        .count();
+    assert_eq!(straddling, 1);
+
+    assert_same(&left, &right, 4, |differ| differ);
+    assert_same(&left, &right, 200, |differ| differ);
+}
+
+#[test]
+fn words() {
+    let (left, right) = sources(2 * CHUNK_SIZE + 1234, 1031);
+
+    assert_same(&left, &right, 8, |differ| differ.word_size(8));
+}
+
+#[test]
+fn padding() {
+    let (left, right) = sources(3 * CHUNK_SIZE + 1234, 4099);
+    let right = &right[..CHUNK_SIZE + 77];
+
+    assert_same(&left, right, 4, |differ| differ.pad(0xff));
+    assert_same(right, &left, 4, |differ| differ.pad(0x00));
+}
+
+#[test]
+fn tail() {
+    let (left, right) = sources(2 * CHUNK_SIZE + 1234, 4099);
+
+    assert_same(&left, &right[..CHUNK_SIZE + 77], 4, |differ| differ);
+    assert_same(&left[..CHUNK_SIZE - 3], &right, 4, |differ| differ);
+}
+
+#[test]
+fn mask() {
+    let (left, right) = sources(3 * CHUNK_SIZE + 1234, 4099);
+
+    assert_same(&left, &right, 4, |differ| {
+        let mask = Mask::parse("0x3fff0..0x40010\n0x7fffe 0f\n0x80001..0x80003 f0").unwrap();
+        differ.mask(mask)
+    });
+}
@@ -0,0 +1,114 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Edits found by the streaming comparison, once both sources shift apart.
+
+use std::io;
+
+use bincmp::{Edit, EditKind, Resync};
+
+use common::data;
+
+mod common;
+
+fn edits(left: &[u8], right: &[u8], window: usize) -> Vec<Edit> {
+    Resync::new(left, right)
+        .window(window)
+        .collect::<io::Result<_>>()
+        .unwrap()
+}
+
+fn edit(kind: EditKind, offset1: u64, len1: u64, offset2: u64, len2: u64) -> Edit {
+    Edit {
+        kind,
+        offset1,
+        len1,
+        offset2,
+        len2,
+    }
+}
+
+#[test]
+fn insert() {
+    let left = data(20000);
+    let mut right = left.clone();
+    right.splice(1000..1000, [0xa5; 50]);
+
+    assert_eq!(
+        edits(&left, &right, 1 << 20),
+        [edit(EditKind::Insert, 1000, 0, 1000, 50)]
+    );
+}
+
+#[test]
+fn delete() {
+    let left = data(20000);
+    let mut right = left.clone();
+    right.drain(1000..1050);
+
+    assert_eq!(
+        edits(&left, &right, 1 << 20),
+        [edit(EditKind::Delete, 1000, 50, 1000, 0)]
+    );
+}
+
+#[test]
+fn replace() {
+    let left = data(20000);
+    let mut right = left.clone();
+    for v in &mut right[1000..1100] {
+        *v = !*v;
+    }
+
+    assert_eq!(
+        edits(&left, &right, 1 << 20),
+        [edit(EditKind::Replace, 1000, 100, 1000, 100)]
+    );
+}
+
+#[test]
+fn shift_within_window() {
+    let left = data(20000);
+    let mut right = left.clone();
+    right.splice(1000..1000, data(3000).into_iter().rev());
+
+    assert_eq!(
+        edits(&left, &right, 4096),
+        [edit(EditKind::Insert, 1000, 0, 1000, 3000)]
+    );
+}
+
+#[test]
+fn shift_beyond_window() {
+    let left = data(20000);
+    let mut right = left.clone();
+    right.splice(1000..1000, data(3000).into_iter().rev());
+
+    // Without an anchor in sight, everything from the shift on is replaced,
+    // the longer tail of the second source being part of the last edit.
+    let found = edits(&left, &right, 256);
+    assert!(found.iter().all(|edit| edit.kind == EditKind::Replace));
+    assert_eq!((found[0].offset1, found[0].offset2), (1000, 1000));
+    for (edit, next) in found.iter().zip(&found[1..]) {
+        assert_eq!(edit.offset1 + edit.len1, next.offset1);
+        assert_eq!(edit.offset2 + edit.len2, next.offset2);
+    }
+    let last = found.last().unwrap();
+    assert_eq!(last.offset1 + last.len1, left.len() as u64);
+    assert_eq!(last.offset2 + last.len2, right.len() as u64);
+}
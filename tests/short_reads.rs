@@ -0,0 +1,141 @@
+/*
+ * bincmp: analyze difference between binaries
+ * Copyright (C) 2023 Eldad Zack
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Sources returning fewer bytes than requested, like pipes do, must compare
+//! the same as sources read in full.
+
+use std::io::{self, Read};
+
+use bincmp::{Differ, Resync, BUFFER_SIZE};
+
+use common::sources;
+
+mod common;
+
+/// Reader returning at most a few bytes at a time, and interrupted now and then.
+struct ShortReader<'a> {
+    data: &'a [u8],
+    max: usize,
+    reads: usize,
+}
+
+impl<'a> ShortReader<'a> {
+    fn new(data: &'a [u8], max: usize) -> Self {
+        Self {
+            data,
+            max,
+            reads: 0,
+        }
+    }
+}
+
+impl Read for ShortReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads += 1;
+        if self.reads.is_multiple_of(5) {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        // Vary the length of the reads so that both sources drift apart.
+        let n = buf
+            .len()
+            .min(self.data.len())
+            .min(1 + self.reads % self.max);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn differences() {
+    let (left, right) = sources(5 * BUFFER_SIZE + 123, 97);
+
+    let expected: Vec<_> = Differ::new(&left[..], &right[..])
+        .collect::<io::Result<_>>()
+        .unwrap();
+    let mut differ = Differ::new(ShortReader::new(&left, 7), ShortReader::new(&right, 300));
+    let found: Vec<_> = (&mut differ).collect::<io::Result<_>>().unwrap();
+
+    assert_eq!(found, expected);
+    assert_eq!(differ.compared(), left.len() as u64);
+    assert_eq!(differ.eof_ordering(), Some(std::cmp::Ordering::Equal));
+}
+
+#[test]
+fn words() {
+    let (left, right) = sources(3 * BUFFER_SIZE + 5, 31);
+
+    let expected: Vec<_> = Differ::new(&left[..], &right[..])
+        .word_size(4)
+        .collect::<io::Result<_>>()
+        .unwrap();
+    let found: Vec<_> = Differ::new(ShortReader::new(&left, 3), ShortReader::new(&right, 11))
+        .word_size(4)
+        .collect::<io::Result<_>>()
+        .unwrap();
+
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn unequal_lengths() {
+    let (left, right) = sources(4 * BUFFER_SIZE, 1000);
+
+    let mut differ = Differ::new(
+        ShortReader::new(&left[..3 * BUFFER_SIZE + 10], 13),
+        ShortReader::new(&right, 5),
+    );
+    let found = (&mut differ).count();
+
+    assert_eq!(found, (3 * BUFFER_SIZE + 10).div_ceil(1000));
+    assert_eq!(differ.compared(), 3 * BUFFER_SIZE as u64 + 10);
+    assert_eq!(differ.eof_ordering(), Some(std::cmp::Ordering::Less));
+}
+
+#[test]
+fn ranges() {
+    let (left, right) = sources(4 * BUFFER_SIZE, 3);
+
+    let expected: Vec<_> = Differ::new(&left[..], &right[..])
+        .ranges(4)
+        .collect::<io::Result<_>>()
+        .unwrap();
+    let found: Vec<_> = Differ::new(ShortReader::new(&left, 17), ShortReader::new(&right, 2))
+        .ranges(4)
+        .collect::<io::Result<_>>()
+        .unwrap();
+
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn resync() {
+    let (left, _) = sources(8 * BUFFER_SIZE, 1);
+    let mut right = left.clone();
+    right.splice(1000..1000, [0xa5; 50]);
+
+    let expected: Vec<_> = Resync::new(&left[..], &right[..])
+        .collect::<io::Result<_>>()
+        .unwrap();
+    let found: Vec<_> = Resync::new(ShortReader::new(&left, 9), ShortReader::new(&right, 4))
+        .collect::<io::Result<_>>()
+        .unwrap();
+
+    assert_eq!(found, expected);
+    assert_eq!(found.len(), 1);
+}